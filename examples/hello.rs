@@ -1,5 +1,4 @@
 use std::fs::File;
-use trash;
 
 fn main() {
     // Let's create and remove a single file
@@ -12,7 +11,7 @@ fn main() {
     for name in the_others.iter() {
         File::create(name).unwrap();
     }
-    trash::delete_all(&the_others).unwrap();
+    trash::delete_all(the_others).unwrap();
     for name in the_others.iter() {
         assert!(File::open(name).is_err());
     }
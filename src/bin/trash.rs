@@ -0,0 +1,343 @@
+//! The `trash` command-line tool.
+//!
+//! This is only built when the `cli` feature is enabled, for example with
+//! `cargo install trash --features cli`. Run `trash help` for the usage.
+
+use std::{ffi::OsString, fmt, io, process};
+
+const USAGE: &str = "\
+Usage: trash <command> [<args>]
+
+Commands:
+    put <path>...                  Move files and folders to the trash
+    list [--json]                  List the items in the trash, oldest first
+    restore [<path>]               Choose items that were deleted from <path> and restore them
+    empty [--older-than <days>]    Permanently remove the items in the trash
+    rm [--force] <pattern>...      Permanently remove the items whose name matches the glob
+                                   pattern, or whose original path is the given path. Asks
+                                   for confirmation when a glob is given, unless --force is
+                                   given
+    help                           Print this message
+";
+
+enum CliError {
+    Usage(String),
+    Input(String),
+    Trash(trash::Error),
+    Io(io::Error),
+}
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::Usage(message) => write!(f, "{}\n\n{}", message, USAGE),
+            CliError::Input(message) => write!(f, "{}", message),
+            CliError::Trash(error) => write!(f, "{}", error),
+            CliError::Io(error) => write!(f, "{}", error),
+        }
+    }
+}
+impl From<trash::Error> for CliError {
+    fn from(error: trash::Error) -> Self {
+        CliError::Trash(error)
+    }
+}
+impl From<io::Error> for CliError {
+    fn from(error: io::Error) -> Self {
+        CliError::Io(error)
+    }
+}
+
+fn main() {
+    let mut args = std::env::args_os().skip(1);
+    let command = args.next();
+    let args: Vec<OsString> = args.collect();
+    let result = match command.as_ref().and_then(|command| command.to_str()) {
+        Some("put") => put(args),
+        #[cfg(any(
+            target_os = "windows",
+            all(
+                unix,
+                not(target_os = "macos"),
+                not(target_os = "ios"),
+                not(target_os = "android")
+            )
+        ))]
+        Some(command @ ("list" | "restore" | "empty" | "rm")) => os_limited::run(command, args),
+        Some("help" | "-h" | "--help") => {
+            print!("{}", USAGE);
+            Ok(())
+        }
+        Some(command) => Err(CliError::Usage(format!("Unknown command `{}`", command))),
+        None => Err(CliError::Usage("No command was given".into())),
+    };
+    if let Err(error) = result {
+        eprintln!("trash: {}", error);
+        process::exit(if let CliError::Usage(_) = error { 2 } else { 1 });
+    }
+}
+
+fn put(paths: Vec<OsString>) -> Result<(), CliError> {
+    if paths.is_empty() {
+        return Err(CliError::Usage("`put` needs at least one path".into()));
+    }
+    trash::delete_all(paths)?;
+    Ok(())
+}
+
+#[cfg(any(
+    target_os = "windows",
+    all(unix, not(target_os = "macos"), not(target_os = "ios"), not(target_os = "android"))
+))]
+mod os_limited {
+    use std::{
+        ffi::{OsStr, OsString},
+        io::{self, BufRead, Write},
+        path::{Path, PathBuf},
+        time::Duration,
+    };
+
+    use chrono::{Local, TimeZone};
+    use trash::{
+        os_limited::{self, TrashQuery},
+        TrashItem,
+    };
+
+    use super::CliError;
+
+    pub(super) fn run(command: &str, args: Vec<OsString>) -> Result<(), CliError> {
+        match command {
+            "list" => list(args),
+            "restore" => restore(args),
+            "empty" => empty(args),
+            "rm" => rm(args),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns all items in the trash, the least recently deleted first.
+    fn sorted_items() -> Result<Vec<TrashItem>, CliError> {
+        let mut items = os_limited::list()?;
+        items.sort_by(|a, b| {
+            a.time_deleted
+                .cmp(&b.time_deleted)
+                .then_with(|| a.original_path().cmp(&b.original_path()))
+        });
+        Ok(items)
+    }
+
+    fn format_time(time_deleted: i64) -> String {
+        match Local.timestamp_opt(time_deleted, 0).single() {
+            Some(time) => time.format("%Y-%m-%d %H:%M:%S").to_string(),
+            None => time_deleted.to_string(),
+        }
+    }
+
+    fn list(args: Vec<OsString>) -> Result<(), CliError> {
+        let json = match args.iter().map(|arg| arg.to_str()).collect::<Vec<_>>()[..] {
+            [] => false,
+            [Some("--json")] => true,
+            _ => return Err(CliError::Usage("`list` only accepts `--json`".into())),
+        };
+        let items = sorted_items()?;
+        if json {
+            // A lossy conversion would print ids and paths that don't exist, so refuse instead.
+            let unicode = |value: &OsStr| {
+                value.to_str().map(str::to_owned).ok_or_else(|| {
+                    CliError::Input(format!(
+                        "{:?} is not valid Unicode and can't be written as JSON",
+                        value
+                    ))
+                })
+            };
+            let items = items
+                .iter()
+                .map(|item| {
+                    Ok(serde_json::json!({
+                        "id": unicode(&item.id)?,
+                        "name": unicode(&item.name)?,
+                        "original_parent": unicode(item.original_parent.as_os_str())?,
+                        "original_path": unicode(item.original_path().as_os_str())?,
+                        "time_deleted": item.time_deleted,
+                    }))
+                })
+                .collect::<Result<Vec<_>, CliError>>()?;
+            let json = serde_json::to_string_pretty(&items).map_err(io::Error::from)?;
+            println!("{}", json);
+        } else {
+            for item in items {
+                println!("{} {}", format_time(item.time_deleted), item.original_path().display());
+            }
+        }
+        Ok(())
+    }
+
+    fn restore(args: Vec<OsString>) -> Result<(), CliError> {
+        let prefix = match &args[..] {
+            [] => None,
+            [path] => Some(absolute(Path::new(path))?),
+            _ => return Err(CliError::Usage("`restore` accepts at most one path".into())),
+        };
+        let items: Vec<_> = sorted_items()?
+            .into_iter()
+            .filter(|item| match &prefix {
+                Some(prefix) => item.original_path().starts_with(prefix),
+                None => true,
+            })
+            .collect();
+        if items.is_empty() {
+            println!("No items to restore");
+            return Ok(());
+        }
+        for (index, item) in items.iter().enumerate() {
+            println!(
+                "{:>4} {} {}",
+                index,
+                format_time(item.time_deleted),
+                item.original_path().display()
+            );
+        }
+        print!("Items to restore, for example `0 2 4-6` (leave empty to cancel): ");
+        io::stdout().flush()?;
+        let mut line = String::new();
+        io::stdin().lock().read_line(&mut line)?;
+        let selection = parse_selection(&line, items.len()).map_err(CliError::Input)?;
+        let selected: Vec<_> = items
+            .into_iter()
+            .enumerate()
+            .filter(|(index, _)| selection.contains(index))
+            .map(|(_, item)| item)
+            .collect();
+        os_limited::restore_all(selected)?;
+        Ok(())
+    }
+
+    /// Parses a list of indices and inclusive ranges, like `0 2 4-6`, separated by spaces or
+    /// commas. Every index must be less than `len`.
+    pub(super) fn parse_selection(input: &str, len: usize) -> Result<Vec<usize>, String> {
+        let parse_index = |s: &str| match s.parse::<usize>() {
+            Ok(index) if index < len => Ok(index),
+            _ => Err(format!("`{}` is not a number between 0 and {}", s, len - 1)),
+        };
+        let mut result = Vec::new();
+        for part in input.split(|c: char| c == ',' || c.is_whitespace()).filter(|s| !s.is_empty()) {
+            match part.split_once('-') {
+                Some((start, end)) => {
+                    let (start, end) = (parse_index(start)?, parse_index(end)?);
+                    if start > end {
+                        return Err(format!("`{}` is not a range from low to high", part));
+                    }
+                    result.extend(start..=end);
+                }
+                None => result.push(parse_index(part)?),
+            }
+        }
+        result.sort_unstable();
+        result.dedup();
+        Ok(result)
+    }
+
+    /// Parses a number of days into the duration they span.
+    pub(super) fn parse_days(days: &str) -> Result<Duration, String> {
+        days.parse::<u64>()
+            .ok()
+            .and_then(|days| days.checked_mul(24 * 60 * 60))
+            .map(Duration::from_secs)
+            .ok_or_else(|| format!("`{}` is not a number of days", days))
+    }
+
+    fn empty(args: Vec<OsString>) -> Result<(), CliError> {
+        match args.iter().map(|arg| arg.to_str()).collect::<Vec<_>>()[..] {
+            [] => os_limited::empty()?,
+            [Some("--older-than"), Some(days)] => {
+                let max_age = parse_days(days).map_err(CliError::Usage)?;
+                let report = os_limited::expire(max_age, false)?;
+                if let Some((_, error)) = report.failed.into_iter().next() {
+                    return Err(error.into());
+                }
+            }
+            _ => return Err(CliError::Usage("`empty` only accepts `--older-than <days>`".into())),
+        }
+        Ok(())
+    }
+
+    fn rm(mut patterns: Vec<OsString>) -> Result<(), CliError> {
+        let force = patterns.first().is_some_and(|arg| arg == "--force");
+        if force {
+            patterns.remove(0);
+        }
+        if patterns.is_empty() {
+            return Err(CliError::Usage("`rm` needs at least one pattern".into()));
+        }
+        let mut selected = Vec::new();
+        for item in os_limited::list()? {
+            let matches = patterns.iter().any(|pattern| {
+                if Path::new(pattern).is_absolute() {
+                    item.original_path() == Path::new(pattern)
+                } else {
+                    TrashQuery::new().name_glob(pattern.to_string_lossy()).matches(&item)
+                }
+            });
+            if matches {
+                selected.push(item);
+            }
+        }
+        if selected.is_empty() {
+            return Err(CliError::Input("No item in the trash matches".into()));
+        }
+        let has_glob = patterns.iter().any(|pattern| !Path::new(pattern).is_absolute());
+        if has_glob && !force {
+            for item in &selected {
+                println!("{} {}", format_time(item.time_deleted), item.original_path().display());
+            }
+            print!("Permanently remove these {} items? [y/N] ", selected.len());
+            io::stdout().flush()?;
+            let mut line = String::new();
+            io::stdin().lock().read_line(&mut line)?;
+            if !matches!(line.trim(), "y" | "Y" | "yes") {
+                return Ok(());
+            }
+        }
+        os_limited::purge_all(selected)?;
+        Ok(())
+    }
+
+    fn absolute(path: &Path) -> Result<PathBuf, CliError> {
+        if path.is_absolute() {
+            Ok(path.to_owned())
+        } else {
+            Ok(std::env::current_dir()?.join(path))
+        }
+    }
+}
+
+#[cfg(test)]
+#[cfg(any(
+    target_os = "windows",
+    all(unix, not(target_os = "macos"), not(target_os = "ios"), not(target_os = "android"))
+))]
+mod tests {
+    use std::time::Duration;
+
+    use super::os_limited::{parse_days, parse_selection};
+
+    #[test]
+    fn test_parse_selection() {
+        assert_eq!(parse_selection("", 3), Ok(vec![]));
+        assert_eq!(parse_selection("2 0,1\n", 3), Ok(vec![0, 1, 2]));
+        assert_eq!(parse_selection("1-3 5 2", 6), Ok(vec![1, 2, 3, 5]));
+        assert!(parse_selection("3", 3).is_err());
+        assert!(parse_selection("a", 3).is_err());
+        assert!(parse_selection("1-", 3).is_err());
+        assert_eq!(parse_selection("2-2", 3), Ok(vec![2]));
+        assert!(parse_selection("2-0", 3).is_err());
+    }
+
+    #[test]
+    fn test_parse_days() {
+        assert_eq!(parse_days("0"), Ok(Duration::ZERO));
+        assert_eq!(parse_days("2"), Ok(Duration::from_secs(2 * 24 * 60 * 60)));
+        assert!(parse_days("-1").is_err());
+        assert!(parse_days("213503982334602").is_err());
+        assert!(parse_days(&u64::MAX.to_string()).is_err());
+    }
+}
@@ -7,286 +7,1283 @@
 //!
 
 use std::{
-    collections::HashSet,
-    ffi::{CStr, CString},
+    collections::{HashMap, HashSet},
+    ffi::{CStr, CString, OsStr, OsString},
+    fmt,
     fs::{create_dir_all, File, OpenOptions},
-    io::{BufRead, BufReader, Write},
-    os::unix::fs::PermissionsExt,
+    io::{self, BufRead, BufReader, Write},
+    os::unix::{
+        ffi::{OsStrExt, OsStringExt},
+        fs::{symlink, DirBuilderExt, MetadataExt, PermissionsExt},
+        io::AsRawFd,
+    },
     path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
 };
 
 use chrono::{NaiveDateTime, TimeZone};
 use log::{debug, error, warn};
 use scopeguard::defer;
 
-use crate::{Error, TrashContext, TrashItem};
+#[path = "freedesktop/fsck.rs"]
+pub mod fsck;
+#[cfg(target_os = "linux")]
+#[path = "freedesktop/watch.rs"]
+pub mod watch;
+
+use crate::{
+    canonicalize_paths,
+    os_limited::{
+        evict_oldest, overwritten_path, renamed_restore_path, QuotaReport, RestoreCollisionPolicy,
+        RestoreOptions, RestoreOutcome,
+    },
+    BatchReport, Error, Progress, TrashContext, TrashItem, TrashItemKind, TrashItemMetadata,
+    TrashOperation,
+};
 
 #[derive(Clone, Default, Debug)]
-pub struct PlatformTrashContext;
+pub(crate) struct PlatformTrashContext {
+    home_trash: Option<PathBuf>,
+    topdirs: Option<Vec<PathBuf>>,
+    cross_device_fallback: bool,
+    target_policy: TrashTargetPolicy,
+}
 impl PlatformTrashContext {
     pub const fn new() -> Self {
-        PlatformTrashContext
+        Self {
+            home_trash: None,
+            topdirs: None,
+            cross_device_fallback: false,
+            target_policy: TrashTargetPolicy::PreferPerMount,
+        }
+    }
+
+    /// Returns the configured home trash or otherwise the one specified by the environment.
+    fn home_trash(&self) -> Result<PathBuf, Error> {
+        match &self.home_trash {
+            Some(path) => Ok(path.clone()),
+            None => home_trash(),
+        }
+    }
+
+    /// Whether items may be copied into a trash folder on another device.
+    fn copies_across_devices(&self) -> bool {
+        self.cross_device_fallback || self.target_policy != TrashTargetPolicy::PreferPerMount
+    }
+
+    /// Returns the folder of [`TrashTargetPolicy::Explicit`] if that's the policy. A relative path
+    /// is ignored here, trashing reports it as an error.
+    fn explicit_trash_folder(&self) -> Option<&Path> {
+        match &self.target_policy {
+            TrashTargetPolicy::Explicit(trash_folder) if trash_folder.is_absolute() => {
+                Some(trash_folder)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the configured topdirs or otherwise the mount points of the system.
+    fn mount_points(&self) -> Result<Vec<MountPoint>, Error> {
+        match &self.topdirs {
+            Some(topdirs) => Ok(topdirs
+                .iter()
+                .map(|topdir| MountPoint {
+                    mnt_dir: topdir.clone(),
+                    mnt_type: String::new(),
+                    mnt_fsname: String::new(),
+                })
+                .collect()),
+            None => get_mount_points(),
+        }
+    }
+}
+pub trait TrashContextExtFreedesktop {
+    /// Sets the home trash folder to use instead of the one determined from the `XDG_DATA_HOME`
+    /// and `HOME` environment variables. `None` restores the default behaviour.
+    fn set_home_trash(&mut self, path: Option<PathBuf>);
+    fn home_trash(&self) -> Option<&Path>;
+
+    /// Sets the list of topdirs (the mount points of the partitions) to use instead of the ones
+    /// read from `/proc/mounts`. `None` restores the default behaviour.
+    ///
+    /// Items that are not under any of the provided topdirs are moved to the home trash. This
+    /// means that providing an empty list makes every item go to the home trash.
+    fn set_topdirs(&mut self, topdirs: Option<Vec<PathBuf>>);
+    fn topdirs(&self) -> Option<&[PathBuf]>;
+
+    /// Enables falling back to the home trash when an item can't be put into the trash folder of
+    /// its own partition, for example because `$topdir/.Trash-$uid` can't be created. This is
+    /// disabled by default.
+    ///
+    /// When enabled and an item can't be renamed into the trash because it's on a different
+    /// device, the item is copied into the trash and then removed from its original location.
+    /// Progress of the copy is reported to the observer set with
+    /// [`TrashContext::set_progress_observer`]. If the copy fails, the partial copy is removed.
+    /// If removing the original fails, the already removed entries are copied back, so the
+    /// original is left intact.
+    fn set_cross_device_fallback(&mut self, enabled: bool);
+    fn cross_device_fallback(&self) -> bool;
+
+    /// Sets which trash folder the deleted items are moved into. The default is
+    /// [`TrashTargetPolicy::PreferPerMount`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use trash::{
+    ///     freedesktop::{TrashContextExtFreedesktop, TrashTargetPolicy},
+    ///     TrashContext,
+    /// };
+    /// let mut trash_ctx = TrashContext::default();
+    /// // Keep the items of removable drives when the drive is unplugged.
+    /// trash_ctx.set_target_policy(TrashTargetPolicy::PreferHome);
+    /// ```
+    fn set_target_policy(&mut self, policy: TrashTargetPolicy);
+    fn target_policy(&self) -> &TrashTargetPolicy;
+
+    /// Same as [`TrashContext::delete`] but returns the [`TrashItem`] that was created in the
+    /// trash for the removed file or directory.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::fs::File;
+    /// use trash::{freedesktop::TrashContextExtFreedesktop, os_limited::restore_all, TrashContext};
+    /// File::create("delete_me_with_undo").unwrap();
+    /// let item = TrashContext::default().delete_returning_item("delete_me_with_undo").unwrap();
+    /// assert_eq!(item.name, "delete_me_with_undo");
+    /// restore_all(vec![item]).unwrap();
+    /// std::fs::remove_file("delete_me_with_undo").unwrap();
+    /// ```
+    fn delete_returning_item<T: AsRef<Path>>(&self, path: T) -> Result<TrashItem, Error>;
+
+    /// Same as [`TrashContext::delete_all`] but returns the [`TrashItem`]s that were created in
+    /// the trash, in the order the paths were provided.
+    fn delete_all_returning_items<I, T>(&self, paths: I) -> Result<Vec<TrashItem>, Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<Path>;
+
+    /// Same as [`Self::delete_all_returning_items`] but either all of the items are moved into the
+    /// trash or none of them.
+    ///
+    /// Before moving anything, every path is checked to exist, to have a parent directory that
+    /// allows removing it, and to have a trash folder that exists or can be created. No folder is
+    /// created until every path passed these checks. If moving an item fails nonetheless, the
+    /// items that were already moved during this call are restored to their original location.
+    ///
+    /// On failure the returned [`BatchReport`] has no succeeded paths. The paths that failed the
+    /// checks or couldn't be moved are in `failed`, along with the paths that were moved but
+    /// couldn't be restored; these are still in the trash. Every other path is in `not_attempted`,
+    /// including the ones that were restored.
+    fn delete_all_atomic<I, T>(&self, paths: I) -> Result<Vec<TrashItem>, BatchReport<PathBuf>>
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<Path>;
+
+    /// Returns every trash folder of the current user that exists: the home trash, and the
+    /// `$topdir/.Trash/$uid` and `$topdir/.Trash-$uid` folders of the mount points.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use trash::{freedesktop::TrashContextExtFreedesktop, TrashContext};
+    /// for folder in TrashContext::default().trash_folders().unwrap() {
+    ///     let size = folder.disk_usage().unwrap();
+    ///     println!("Trash on {:?} ({}): {} items, {} bytes", folder.topdir, folder.fs_type, folder.item_count, size);
+    /// }
+    /// ```
+    fn trash_folders(&self) -> Result<Vec<TrashFolder>, Error>;
+
+    /// Permanently removes the least recently deleted items of a single trash folder until the
+    /// items left in it occupy at most `max_bytes`. `trash_folder` is the `path` of one of the
+    /// folders returned by [`trash_folders`](Self::trash_folders), any other path is rejected with
+    /// [`Error::NotATrashFolder`].
+    ///
+    /// See [`crate::os_limited::enforce_quota`] for the details.
+    fn enforce_folder_quota(
+        &self,
+        trash_folder: &Path,
+        max_bytes: u64,
+    ) -> Result<QuotaReport, Error>;
+
+    /// Removes everything from a single trash folder. `trash_folder` is the `path` of one of the
+    /// valid folders returned by [`trash_folders`](Self::trash_folders). Any other path, including
+    /// the one of an invalid trash folder, is rejected with [`Error::NotATrashFolder`].
+    ///
+    /// See [`crate::os_limited::empty`] for the details.
+    fn empty_folder(&self, trash_folder: &Path) -> Result<(), Error>;
+
+    /// Checks the valid trash folders for inconsistencies that listing, purging, and restoring
+    /// items silently skip or trip over, and repairs them if `repair` is true. See
+    /// [`fsck::Problem`] for the kinds of problems and how each of them is repaired.
+    ///
+    /// Nothing that may contain user data is removed when repairing: such files are moved into
+    /// the `quarantine` folder of the trash folder instead, where they are left for inspection
+    /// until the trash folder is emptied.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use trash::{freedesktop::TrashContextExtFreedesktop, TrashContext};
+    /// for finding in TrashContext::default().fsck(false).unwrap() {
+    ///     println!("{:?}", finding.problem);
+    /// }
+    /// ```
+    fn fsck(&self, repair: bool) -> Result<Vec<fsck::Finding>, Error>;
+
+    /// Starts watching the trash folders for items that are added or removed, see
+    /// [`watch::TrashWatcher`]. The watcher uses a copy of this context.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use trash::{freedesktop::{watch::TrashEvent, TrashContextExtFreedesktop}, TrashContext};
+    /// let watcher = TrashContext::default().watch().unwrap();
+    /// println!("{} items in the trash", watcher.items().count());
+    /// for event in watcher {
+    ///     match event.unwrap() {
+    ///         TrashEvent::Added(item) => println!("Trashed {:?}", item.original_path()),
+    ///         TrashEvent::Removed(item) => println!("Restored or purged {:?}", item.original_path()),
+    ///     }
+    /// }
+    /// ```
+    #[cfg(target_os = "linux")]
+    fn watch(&self) -> Result<watch::TrashWatcher, Error>;
+}
+impl TrashContextExtFreedesktop for TrashContext {
+    fn set_home_trash(&mut self, path: Option<PathBuf>) {
+        self.platform_specific.home_trash = path;
+    }
+    fn home_trash(&self) -> Option<&Path> {
+        self.platform_specific.home_trash.as_deref()
+    }
+    fn set_topdirs(&mut self, topdirs: Option<Vec<PathBuf>>) {
+        self.platform_specific.topdirs = topdirs;
+    }
+    fn topdirs(&self) -> Option<&[PathBuf]> {
+        self.platform_specific.topdirs.as_deref()
+    }
+    fn set_cross_device_fallback(&mut self, enabled: bool) {
+        self.platform_specific.cross_device_fallback = enabled;
+    }
+    fn cross_device_fallback(&self) -> bool {
+        self.platform_specific.cross_device_fallback
+    }
+    fn set_target_policy(&mut self, policy: TrashTargetPolicy) {
+        self.platform_specific.target_policy = policy;
+    }
+    fn target_policy(&self) -> &TrashTargetPolicy {
+        &self.platform_specific.target_policy
+    }
+    fn delete_returning_item<T: AsRef<Path>>(&self, path: T) -> Result<TrashItem, Error> {
+        // `delete_all_returning_items` returns exactly one item for each path on success.
+        Ok(self.delete_all_returning_items(&[path])?.pop().unwrap())
+    }
+    fn delete_all_returning_items<I, T>(&self, paths: I) -> Result<Vec<TrashItem>, Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<Path>,
+    {
+        let full_paths = canonicalize_paths(paths)?;
+        self.delete_all_canonicalized_returning_items(full_paths)
+    }
+    fn delete_all_atomic<I, T>(&self, paths: I) -> Result<Vec<TrashItem>, BatchReport<PathBuf>>
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<Path>,
+    {
+        let paths = paths.into_iter().map(|path| path.as_ref().to_owned()).collect();
+        self.delete_all_atomic_paths(paths)
+    }
+    fn trash_folders(&self) -> Result<Vec<TrashFolder>, Error> {
+        trash_folders(self)
+    }
+    fn enforce_folder_quota(
+        &self,
+        trash_folder: &Path,
+        max_bytes: u64,
+    ) -> Result<QuotaReport, Error> {
+        let trash_folder = known_trash_folder(self, trash_folder)?.path;
+        let items = list(self)?.into_iter().filter(|item| {
+            trash_folder_and_name(item).is_ok_and(|(folder, _)| folder == trash_folder)
+        });
+        evict_oldest(self, items.collect(), max_bytes)
+    }
+    fn empty_folder(&self, trash_folder: &Path) -> Result<(), Error> {
+        let folder = known_trash_folder(self, trash_folder)?;
+        // An invalid `.Trash` folder may be a symbolic link to a folder that isn't a trash at all.
+        if folder.validity != TrashValidity::Valid {
+            return Err(Error::NotATrashFolder { path: trash_folder.to_owned() });
+        }
+        empty_trash_folder(&folder.path)
+    }
+    fn fsck(&self, repair: bool) -> Result<Vec<fsck::Finding>, Error> {
+        fsck::fsck(self, repair)
+    }
+    #[cfg(target_os = "linux")]
+    fn watch(&self) -> Result<watch::TrashWatcher, Error> {
+        watch::TrashWatcher::new(self)
     }
 }
 impl TrashContext {
     pub(crate) fn delete_all_canonicalized(&self, full_paths: Vec<PathBuf>) -> Result<(), Error> {
-        let root = Path::new("/");
-        let home_trash = home_trash()?;
-        let mount_points = get_mount_points()?;
-        let uid = unsafe { libc::getuid() };
+        self.delete_all_canonicalized_returning_items(full_paths).map(|_| ())
+    }
+
+    fn delete_all_canonicalized_returning_items(
+        &self,
+        full_paths: Vec<PathBuf>,
+    ) -> Result<Vec<TrashItem>, Error> {
+        let home_trash = self.platform_specific.home_trash()?;
+        let mount_points = self.platform_specific.mount_points()?;
+        let mut items = Vec::with_capacity(full_paths.len());
+        let mut tracker = ProgressTracker::new(self, TrashOperation::Delete);
         for path in full_paths {
             debug!("Deleting {:?}", path);
-            let mut topdir = None;
-            for mount_point in mount_points.iter() {
-                if mount_point.mnt_dir == root {
-                    continue;
+            self.check_cancelled()?;
+            let (trash_folder, topdir) =
+                self.trash_folder_for(&path, &home_trash, &mount_points, true)?;
+            items.push(move_to_trash(self, &path, trash_folder, topdir, &mut tracker)?);
+            tracker.item_done(&path);
+        }
+        Ok(items)
+    }
+
+    fn delete_all_atomic_paths(
+        &self,
+        paths: Vec<PathBuf>,
+    ) -> Result<Vec<TrashItem>, BatchReport<PathBuf>> {
+        if paths.is_empty() {
+            return Ok(Vec::new());
+        }
+        let locations = self
+            .platform_specific
+            .home_trash()
+            .and_then(|home_trash| Ok((home_trash, self.platform_specific.mount_points()?)));
+        let (home_trash, mount_points) = match locations {
+            Ok(locations) => locations,
+            Err(error) => return Err(atomic_failure_report(paths, vec![(0, error)])),
+        };
+
+        // Validate everything up front, so that a rollback is only needed in exceptional cases.
+        // Nothing is created yet, as that would have to be undone if a later path is invalid.
+        let mut full_paths = Vec::with_capacity(paths.len());
+        let mut errors = Vec::new();
+        for (index, path) in paths.iter().enumerate() {
+            let validated = canonicalize_paths([path]).and_then(|mut canonical| {
+                let full_path = canonical.remove(0);
+                validate_deletable(&full_path)?;
+                self.trash_folder_for(&full_path, &home_trash, &mount_points, false)?;
+                Ok(full_path)
+            });
+            match validated {
+                Ok(full_path) => full_paths.push(full_path),
+                Err(error) => errors.push((index, error)),
+            }
+        }
+        if !errors.is_empty() {
+            return Err(atomic_failure_report(paths, errors));
+        }
+
+        let mut items = Vec::with_capacity(full_paths.len());
+        let mut tracker = ProgressTracker::new(self, TrashOperation::Delete);
+        for (index, path) in full_paths.iter().enumerate() {
+            debug!("Deleting {:?}", path);
+            let moved = self
+                .check_cancelled()
+                .and_then(|_| self.trash_folder_for(path, &home_trash, &mount_points, true))
+                .and_then(|(trash_folder, topdir)| {
+                    move_to_trash(self, path, trash_folder, topdir, &mut tracker)
+                });
+            match moved {
+                Ok(item) => {
+                    items.push(item);
+                    tracker.item_done(path);
+                }
+                Err(error) => {
+                    let mut errors = vec![(index, error)];
+                    // Restore in reverse order, so that parents are restored before their children.
+                    for (index, item) in items.into_iter().enumerate().rev() {
+                        let mut tracker = ProgressTracker::silent(TrashOperation::Restore);
+                        match restore_one(&item, RestoreCollisionPolicy::Fail, &mut tracker) {
+                            RestoreOutcome::Restored { .. } => {}
+                            RestoreOutcome::Collision { path } => {
+                                let error =
+                                    Error::RestoreCollision { path, remaining_items: vec![item] };
+                                errors.push((index, error));
+                            }
+                            RestoreOutcome::Failed(error) => errors.push((index, error)),
+                            RestoreOutcome::Skipped { .. } | RestoreOutcome::NotAttempted => {
+                                unreachable!("Skipped or not attempted with the `Fail` policy")
+                            }
+                        }
+                    }
+                    return Err(atomic_failure_report(paths, errors));
                 }
-                if path.starts_with(&mount_point.mnt_dir) {
-                    topdir = Some(&mount_point.mnt_dir);
-                    break;
+            }
+        }
+        Ok(items)
+    }
+
+    /// Returns the trash folder that `path` should be moved into along with the topdir that the
+    /// path is stored relative to. The trash folder is created if it doesn't exist and `create` is
+    /// true, otherwise it's only checked that it could be created.
+    fn trash_folder_for(
+        &self,
+        path: &Path,
+        home_trash: &Path,
+        mount_points: &[MountPoint],
+        create: bool,
+    ) -> Result<(PathBuf, PathBuf), Error> {
+        let root = Path::new("/");
+        let trash_folder = match &self.platform_specific.target_policy {
+            TrashTargetPolicy::PreferPerMount => {
+                // Mount points may be nested, so pick the deepest one that contains the path.
+                let topdir = mount_points
+                    .iter()
+                    .map(|mount_point| mount_point.mnt_dir.as_path())
+                    .filter(|mnt_dir| *mnt_dir != root && path.starts_with(mnt_dir))
+                    .max_by_key(|mnt_dir| mnt_dir.components().count())
+                    .unwrap_or(root);
+                debug!("The topdir of this file is {:?}", topdir);
+                if topdir != root {
+                    let uid = unsafe { libc::getuid() };
+                    match mounted_trash_folder(uid, topdir, create) {
+                        Ok(trash_folder) => return Ok((trash_folder, topdir.to_owned())),
+                        Err(error) if self.platform_specific.cross_device_fallback => {
+                            warn!("Could not use the trash folder of {:?}, so falling back to the home trash. The error was: {:?}", topdir, error);
+                        }
+                        Err(error) => return Err(error),
+                    }
                 }
+                home_trash
             }
-            let topdir = if let Some(t) = topdir { t } else { root };
-            debug!("The topdir of this file is {:?}", topdir);
-            if topdir == root {
-                // Note that the following function creates the trash folder
-                // and its required subfolders in case they don't exist.
-                move_to_trash(path, &home_trash, topdir)?;
-            } else {
-                execute_on_mounted_trash_folders(uid, topdir, true, true, |trash_path| {
-                    move_to_trash(&path, trash_path, topdir)
-                })?;
+            TrashTargetPolicy::PreferHome => home_trash,
+            TrashTargetPolicy::Explicit(trash_folder) if trash_folder.is_relative() => {
+                let source = io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "the folder of `TrashTargetPolicy::Explicit` must be an absolute path",
+                );
+                return Err(fsys_err(trash_folder, source));
             }
+            TrashTargetPolicy::Explicit(trash_folder) => trash_folder.as_path(),
+        };
+        if create {
+            prepare_trash_folder(trash_folder)?;
+        } else {
+            check_creatable(&trash_folder.join("files"))?;
+            check_creatable(&trash_folder.join("info"))?;
         }
-        Ok(())
+        // Items outside of `$topdir/.Trash*` folders are stored with their absolute path.
+        Ok((trash_folder.to_owned(), root.to_owned()))
     }
 }
 
-pub fn list() -> Result<Vec<TrashItem>, Error> {
+/// Builds the report of a failed [`TrashContextExtFreedesktop::delete_all_atomic`] call from the
+/// errors of the paths at the given indices. All other paths are reported as not attempted.
+fn atomic_failure_report(
+    paths: Vec<PathBuf>,
+    mut errors: Vec<(usize, Error)>,
+) -> BatchReport<PathBuf> {
+    errors.sort_by_key(|(index, _)| *index);
+    let mut errors = errors.into_iter().peekable();
+    let mut report =
+        BatchReport { succeeded: Vec::new(), failed: Vec::new(), not_attempted: Vec::new() };
+    for (index, path) in paths.into_iter().enumerate() {
+        match errors.next_if(|(error_index, _)| *error_index == index) {
+            Some((_, error)) => report.failed.push((path, error)),
+            None => report.not_attempted.push(path),
+        }
+    }
+    report
+}
+
+/// Checks that `path` exists and that its parent directory allows removing it.
+fn validate_deletable(path: &Path) -> Result<(), Error> {
+    path.symlink_metadata().map_err(|e| fsys_err(path, e))?;
+    check_writable(path.parent().ok_or(Error::TargetedRoot)?)
+}
+
+/// Checks that `folder` exists, or that the closest of its ancestors that exists allows creating
+/// it, and that entries can be created in it.
+fn check_creatable(folder: &Path) -> Result<(), Error> {
+    let existing = folder.ancestors().find(|ancestor| ancestor.exists()).unwrap_or(folder);
+    check_writable(existing)
+}
+
+/// Checks that entries can be created in and removed from the directory at `dir`.
+fn check_writable(dir: &Path) -> Result<(), Error> {
+    let dir_cstr = CString::new(dir.as_os_str().as_bytes())
+        .map_err(|_| Error::ConvertOsString { original: dir.as_os_str().to_owned() })?;
+    if unsafe { libc::access(dir_cstr.as_ptr(), libc::W_OK | libc::X_OK) } != 0 {
+        return Err(fsys_err(dir, io::Error::last_os_error()));
+    }
+    Ok(())
+}
+
+pub(crate) fn list(ctx: &TrashContext) -> Result<Vec<TrashItem>, Error> {
+    let mut iter = list_iter(ctx)?;
+    let mut result = Vec::new();
+    while let Some(item) = iter.next() {
+        match item {
+            Ok(item) => result.push(item),
+            Err(error) if iter.folder_failed => return Err(error),
+            Err(error) => warn!("Skipping an item of the trash. The error was: {}", error),
+        }
+    }
+    Ok(result)
+}
+
+pub(crate) fn list_iter(ctx: &TrashContext) -> Result<ListIter, Error> {
+    let trash_folders = listed_trash_folders(ctx)?;
+    Ok(ListIter { trash_folders: trash_folders.into_iter(), current: None, folder_failed: false })
+}
+
+/// Returns the trash folders whose items are listed: the home trash, the valid trash folders of
+/// the mount points, and the folder of [`TrashTargetPolicy::Explicit`].
+fn listed_trash_folders(ctx: &TrashContext) -> Result<Vec<PathBuf>, Error> {
     let mut trash_folders = HashSet::new();
     // Get home trash folder and add it to the set of trash folders.
     // It may not exist and that's completely fine as long as there are other trash folders.
-    let home_error;
-    match home_trash() {
+    let home_error = match ctx.platform_specific.home_trash() {
         Ok(home_trash) => {
-            trash_folders.insert(home_trash);
-            home_error = None;
-        }
-        Err(e) => {
-            home_error = Some(e);
+            if home_trash.is_dir() {
+                trash_folders.insert(home_trash);
+            }
+            None
         }
-    }
+        Err(e) => Some(e),
+    };
     // Get all mountpoints and attemt to find a trash folder in each adding them to the SET of
     // trash folders when found one.
     let uid = unsafe { libc::getuid() };
-    let mount_points = get_mount_points()?;
+    let mount_points = ctx.platform_specific.mount_points()?;
     for mount in mount_points.into_iter() {
         execute_on_mounted_trash_folders(uid, &mount.mnt_dir, false, false, |trash_path| {
             trash_folders.insert(trash_path);
             Ok(())
         })?;
     }
+    if let Some(trash_folder) = ctx.platform_specific.explicit_trash_folder() {
+        if trash_folder.is_dir() {
+            trash_folders.insert(trash_folder.to_owned());
+        }
+    }
     if trash_folders.is_empty() {
         warn!(
             "No trash folder was found. The error when looking for the 'home trash' was: {:?}",
             home_error
         );
-        return Ok(vec![]);
     }
-    // List all items from the set of trash folders
-    let mut result = Vec::new();
-    for folder in trash_folders.iter() {
-        // Read the info files for every file
-        let trash_folder_parent = folder.parent().unwrap();
-        let info_folder = folder.join("info");
-        let read_dir =
-            std::fs::read_dir(&info_folder).map_err(|e| fsys_err_to_unknown(&info_folder, e))?;
-        'trash_item: for entry in read_dir {
-            let info_entry;
-            match entry {
-                Ok(entry) => info_entry = entry,
-                Err(e) => {
-                    // Another thread or process may have removed that entry by now
-                    debug!("Tried resolving the trash info `DirEntry` but it failed with: '{}'", e);
-                    continue;
-                }
-            }
-            // Entrt should really be an info file but better safe than sorry
-            let file_type;
-            match info_entry.file_type() {
-                Ok(f_type) => file_type = f_type,
-                Err(e) => {
-                    // Another thread or process may have removed that entry by now
-                    debug!("Tried getting the file type of the trash info `DirEntry` but failed with: {}", e);
-                    continue;
-                }
-            }
-            let info_path = info_entry.path();
-            if !file_type.is_file() {
-                warn!("Found an item that's not a file, among the trash info files. This is unexpected. The path to the item is: '{:?}'", info_path);
-                continue;
-            }
-            let info_file;
-            match File::open(&info_path) {
-                Ok(file) => info_file = file,
-                Err(e) => {
-                    // Another thread or process may have removed that entry by now
-                    debug!("Tried opening the trash info '{:?}' but failed with: {}", info_path, e);
-                    continue;
-                }
-            }
-            let id = info_path.clone().into();
-            let mut name = None;
-            let mut original_parent: Option<PathBuf> = None;
-            let mut time_deleted = None;
-
-            let info_reader = BufReader::new(info_file);
-            // Skip 1 because the first line must be "[Trash Info]"
-            'info_lines: for line_result in info_reader.lines().skip(1) {
-                // Another thread or process may have removed the infofile by now
-                let line = if let Ok(line) = line_result {
-                    line
-                } else {
-                    break 'info_lines;
-                };
-                let mut split = line.split('=');
+    Ok(trash_folders.into_iter().collect())
+}
 
-                // Just unwraping here because the system is assumed to follow the specification.
-                let key = split.next().unwrap().trim();
-                let value = split.next().unwrap().trim();
+/// Reads the info files of the trash folders one by one.
+pub(crate) struct ListIter {
+    trash_folders: std::vec::IntoIter<PathBuf>,
+    /// The topdir and the entries of the info folder of the trash folder that's being read.
+    current: Option<(PathBuf, std::fs::ReadDir)>,
+    /// Whether the last error was returned because an info folder couldn't be read.
+    folder_failed: bool,
+}
+impl Iterator for ListIter {
+    type Item = Result<TrashItem, Error>;
 
-                if key == "Path" {
-                    let mut value_path = Path::new(value).to_owned();
-                    if value_path.is_relative() {
-                        value_path = trash_folder_parent.join(value_path);
-                    }
-                    let full_path_utf8 = PathBuf::from(parse_uri_path(&value_path));
-                    name = Some(full_path_utf8.file_name().unwrap().to_str().unwrap().to_owned());
-                    let parent = full_path_utf8.parent().unwrap();
-                    original_parent = Some(parent.into());
-                } else if key == "DeletionDate" {
-                    let parsed_time = NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S");
-                    let naive_local = match parsed_time {
-                        Ok(t) => t,
+    fn next(&mut self) -> Option<Self::Item> {
+        self.folder_failed = false;
+        loop {
+            let (topdir, read_dir) = match &mut self.current {
+                Some(current) => current,
+                None => {
+                    let folder = self.trash_folders.next()?;
+                    let topdir = trash_folder_topdir(&folder).to_owned();
+                    let info_folder = folder.join("info");
+                    match std::fs::read_dir(&info_folder) {
+                        Ok(read_dir) => self.current.insert((topdir, read_dir)),
                         Err(e) => {
-                            error!("Failed to parse the deletion date of the trash item {:?}. The deletion date was '{}'. Parse error was: {:?}", name, value, e);
-                            continue 'trash_item;
-                        }
-                    };
-                    let time = chrono::Local.from_local_datetime(&naive_local).earliest();
-                    match time {
-                        Some(time) => time_deleted = Some(time.timestamp()),
-                        None => {
-                            error!("Failed to convert the local time to a UTC time. Local time was {:?}", naive_local);
-                            continue 'trash_item;
+                            self.folder_failed = true;
+                            return Some(Err(fsys_err(&info_folder, e)));
                         }
                     }
                 }
-            }
-            if let Some(name) = name {
-                if let Some(original_parent) = original_parent {
-                    if let Some(time_deleted) = time_deleted {
-                        result.push(TrashItem { id, name, original_parent, time_deleted });
-                    } else {
-                        warn!("Could not determine the deletion time of the trash item. (The `DeletionDate` field is probably missing from the info file.) The info file path is: '{:?}'", info_path);
-                    }
-                } else {
-                    warn!("Could not determine the original parent folder of the trash item. (The `Path` field is probably missing from the info file.) The info file path is: '{:?}'", info_path);
+            };
+            let entry = match read_dir.next() {
+                Some(entry) => entry,
+                None => {
+                    self.current = None;
+                    continue;
                 }
-            } else {
-                warn!("Could not determine the name of the trash item. (The `Path` field is probably missing from the info file.) The info file path is: '{:?}'", info_path);
+            };
+            if let Some(item) = read_info_entry(topdir, entry) {
+                return Some(item);
             }
         }
     }
+}
+
+/// Returns `None` if the entry should be skipped, for example because it was removed since.
+fn read_info_entry(
+    topdir: &Path,
+    entry: io::Result<std::fs::DirEntry>,
+) -> Option<Result<TrashItem, Error>> {
+    let info_entry = match entry {
+        Ok(entry) => entry,
+        Err(e) => {
+            // Another thread or process may have removed that entry by now
+            debug!("Tried resolving the trash info `DirEntry` but it failed with: '{}'", e);
+            return None;
+        }
+    };
+    // Entrt should really be an info file but better safe than sorry
+    let file_type = match info_entry.file_type() {
+        Ok(f_type) => f_type,
+        Err(e) => {
+            // Another thread or process may have removed that entry by now
+            debug!(
+                "Tried getting the file type of the trash info `DirEntry` but failed with: {}",
+                e
+            );
+            return None;
+        }
+    };
+    let info_path = info_entry.path();
+    if !file_type.is_file() {
+        warn!("Found an item that's not a file, among the trash info files. This is unexpected. The path to the item is: '{:?}'", info_path);
+        return None;
+    }
+    let info = match TrashInfo::read(&info_path) {
+        Ok(info) => info,
+        Err(Error::FileSystem { source, .. }) if source.kind() == io::ErrorKind::NotFound => {
+            // Another thread or process may have removed that entry by now
+            debug!("Tried reading the trash info {:?} but it was removed", info_path);
+            return None;
+        }
+        Err(e) => return Some(Err(e)),
+    };
+    Some(item_from_info(topdir, info_path, &info))
+}
+
+/// Creates the item that's described by the `info` read from `info_path`.
+fn item_from_info(topdir: &Path, info_path: PathBuf, info: &TrashInfo) -> Result<TrashItem, Error> {
+    let original_path =
+        if info.path.is_relative() { topdir.join(&info.path) } else { info.path.clone() };
+    let name = match original_path.file_name() {
+        Some(name) => name.to_owned(),
+        None => {
+            return Err(Error::InvalidTrashInfo {
+                reason: format!("the original path {:?} has no name", original_path),
+                path: info_path,
+            });
+        }
+    };
+    let original_parent = original_path.parent().unwrap_or(&original_path).to_owned();
+    let time_deleted = match info.time_deleted() {
+        Some(time_deleted) => time_deleted,
+        None => {
+            return Err(Error::InvalidTrashInfo {
+                reason: format!(
+                    "the DeletionDate {:?} doesn't exist in the local time zone",
+                    info.deletion_date
+                ),
+                path: info_path,
+            });
+        }
+    };
+    Ok(TrashItem { id: info_path.into(), name, original_parent, time_deleted })
+}
+
+pub(crate) fn item_from_id(ctx: &TrashContext, id: &OsStr) -> Result<TrashItem, Error> {
+    let info_path = Path::new(id);
+    let not_in_trash = || Error::CouldNotAccess { target: info_path.to_string_lossy().into() };
+    let is_info_file = info_path.extension() == Some(OsStr::new("trashinfo"))
+        && info_path.parent().and_then(Path::file_name) == Some(OsStr::new("info"));
+    if !is_info_file {
+        return Err(not_in_trash());
+    }
+    let trash_folder = info_path.parent().and_then(Path::parent).unwrap();
+    if !listed_trash_folders(ctx)?.iter().any(|folder| folder == trash_folder) {
+        return Err(not_in_trash());
+    }
+    let info = match TrashInfo::read(info_path) {
+        Ok(info) => info,
+        Err(Error::FileSystem { source, .. }) if source.kind() == io::ErrorKind::NotFound => {
+            return Err(not_in_trash());
+        }
+        Err(e) => return Err(e),
+    };
+    let file = trash_folder.join("files").join(info_path.file_stem().unwrap());
+    if file.symlink_metadata().is_err() {
+        return Err(not_in_trash());
+    }
+    item_from_info(trash_folder_topdir(trash_folder), info_path.to_owned(), &info)
+}
+
+/// The kind of a [`TrashFolder`], see the "Trash directories" section of the specification.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TrashFolderKind {
+    /// The home trash, usually `~/.local/share/Trash`.
+    Home,
+
+    /// A `$topdir/.Trash/$uid` folder inside a trash folder set up by an administrator.
+    Admin,
+
+    /// A `$topdir/.Trash-$uid` folder.
+    User,
+
+    /// The folder of [`TrashTargetPolicy::Explicit`].
+    Explicit,
+}
+
+/// A trash folder of the current user.
+///
+/// See [`TrashContextExtFreedesktop::trash_folders`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TrashFolder {
+    /// The path of the trash folder, which contains the `files` and `info` folders.
+    pub path: PathBuf,
+
+    pub kind: TrashFolderKind,
+
+    /// The mount point of the partition that contains the trash folder.
+    pub topdir: PathBuf,
+
+    /// The type of the file system mounted at `topdir`, for example `ext4` or `vfat`. This is
+    /// empty if the topdirs were configured with [`TrashContextExtFreedesktop::set_topdirs`].
+    pub fs_type: String,
+
+    /// The device or remote file system mounted at `topdir`, for example `/dev/sdb1`. This is
+    /// empty if the topdirs were configured with [`TrashContextExtFreedesktop::set_topdirs`].
+    pub fs_name: String,
+
+    /// For [`TrashFolderKind::Admin`] folders this is the validity of `$topdir/.Trash`. The items
+    /// of invalid trash folders are not listed and nothing is trashed into them. Other kinds of
+    /// trash folders are always valid.
+    pub validity: TrashValidity,
+
+    /// The number of info files in the `info` folder, which is the number of items in the trash
+    /// folder.
+    pub item_count: usize,
+}
+impl TrashFolder {
+    /// Returns the disk space used by the items of this trash folder in bytes. The
+    /// `directorysizes` cache is used for the directories when it's up to date.
+    pub fn disk_usage(&self) -> Result<u64, Error> {
+        let files_folder = self.path.join("files");
+        let read_dir = match std::fs::read_dir(&files_folder) {
+            Ok(read_dir) => read_dir,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(fsys_err(&files_folder, e)),
+        };
+        let sizes = directory_sizes_by_name(&self.path);
+        let mut result = 0;
+        for entry in read_dir {
+            let entry = entry.map_err(|e| fsys_err(&files_folder, e))?;
+            let cached = cached_directory_size(&self.path, &entry.file_name(), &sizes);
+            result += match cached {
+                Some(size) => size,
+                None => disk_usage(&entry.path()).map_err(|e| fsys_err(entry.path(), e))?,
+            };
+        }
+        Ok(result)
+    }
+}
+
+/// Returns the trash folder that `path` refers to, as it's returned by [`trash_folders`], or an
+/// [`Error::NotATrashFolder`] if it's none of them.
+fn known_trash_folder(ctx: &TrashContext, path: &Path) -> Result<TrashFolder, Error> {
+    let canonical = path
+        .canonicalize()
+        .map_err(|source| Error::CanonicalizePath { original: path.to_owned(), source })?;
+    trash_folders(ctx)?
+        .into_iter()
+        .find(|folder| folder.path.canonicalize().is_ok_and(|folder| folder == canonical))
+        .ok_or_else(|| Error::NotATrashFolder { path: path.to_owned() })
+}
+
+/// Returns the trash folders that exist for the current user, including invalid ones.
+fn trash_folders(ctx: &TrashContext) -> Result<Vec<TrashFolder>, Error> {
+    let mount_points = ctx.platform_specific.mount_points()?;
+    let root_mount =
+        MountPoint { mnt_dir: "/".into(), mnt_type: String::new(), mnt_fsname: String::new() };
+    // Mount points may be nested, so pick the deepest one that contains the path.
+    let mount_point_of = |path: &Path| {
+        mount_points
+            .iter()
+            .filter(|mount_point| path.starts_with(&mount_point.mnt_dir))
+            .max_by_key(|mount_point| mount_point.mnt_dir.components().count())
+            .unwrap_or(&root_mount)
+    };
+    let folder = |path: PathBuf, kind, mount_point: &MountPoint, validity| -> Result<_, Error> {
+        let item_count = count_info_files(&path)?;
+        Ok(TrashFolder {
+            path,
+            kind,
+            topdir: mount_point.mnt_dir.clone(),
+            fs_type: mount_point.mnt_type.clone(),
+            fs_name: mount_point.mnt_fsname.clone(),
+            validity,
+            item_count,
+        })
+    };
+
+    let mut result = Vec::new();
+    let home_trash = ctx.platform_specific.home_trash()?;
+    if home_trash.is_dir() {
+        let mount_point = mount_point_of(&home_trash);
+        result.push(folder(home_trash, TrashFolderKind::Home, mount_point, TrashValidity::Valid)?);
+    }
+    let uid = unsafe { libc::getuid() };
+    for mount_point in mount_points.iter() {
+        let admin_trash = mount_point.mnt_dir.join(".Trash");
+        let users_trash = admin_trash.join(uid.to_string());
+        if users_trash.is_dir() {
+            let validity = folder_validity(&admin_trash)?;
+            result.push(folder(users_trash, TrashFolderKind::Admin, mount_point, validity)?);
+        }
+        let user_trash = mount_point.mnt_dir.join(format!(".Trash-{}", uid));
+        if user_trash.is_dir() {
+            result.push(folder(
+                user_trash,
+                TrashFolderKind::User,
+                mount_point,
+                TrashValidity::Valid,
+            )?);
+        }
+    }
+    if let Some(trash_folder) = ctx.platform_specific.explicit_trash_folder() {
+        let is_listed = result.iter().any(|folder| folder.path == trash_folder);
+        if trash_folder.is_dir() && !is_listed {
+            let mount_point = mount_point_of(trash_folder);
+            result.push(folder(
+                trash_folder.to_owned(),
+                TrashFolderKind::Explicit,
+                mount_point,
+                TrashValidity::Valid,
+            )?);
+        }
+    }
+    Ok(result)
+}
+
+fn count_info_files(trash_folder: &Path) -> Result<usize, Error> {
+    let info_folder = trash_folder.join("info");
+    let read_dir = match std::fs::read_dir(&info_folder) {
+        Ok(read_dir) => read_dir,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(fsys_err(&info_folder, e)),
+    };
+    let mut result = 0;
+    for entry in read_dir {
+        let entry = entry.map_err(|e| fsys_err(&info_folder, e))?;
+        if Path::new(&entry.file_name()).extension() == Some(OsStr::new("trashinfo")) {
+            result += 1;
+        }
+    }
     Ok(result)
 }
 
-pub fn purge_all<I>(items: I) -> Result<(), Error>
+/// Returns the trash folder that contains the item and the name of the item inside the `files`
+/// folder of the trash.
+///
+/// The `id` of a deserialized item can be anything, an [`Error::CouldNotAccess`] is returned if it
+/// isn't the path of a `.trashinfo` file inside an `info` folder.
+fn trash_folder_and_name(item: &TrashItem) -> Result<(&Path, &OsStr), Error> {
+    // The "in-trash" filename must be parsed from the trashinfo filename
+    // which is the filename in the `id` field.
+    let info_file = Path::new(&item.id);
+    let is_info_file = info_file.extension() == Some(OsStr::new("trashinfo"))
+        && info_file.parent().and_then(Path::file_name) == Some(OsStr::new("info"));
+    match (info_file.parent().and_then(Path::parent), info_file.file_stem()) {
+        (Some(trash_folder), Some(name_in_trash)) if is_info_file => {
+            Ok((trash_folder, name_in_trash))
+        }
+        _ => Err(Error::CouldNotAccess { target: info_file.to_string_lossy().into() }),
+    }
+}
+
+pub(crate) fn metadata(item: &TrashItem) -> Result<TrashItemMetadata, Error> {
+    let (trash_folder, _) = trash_folder_and_name(item)?;
+    metadata_with_sizes(item, &directory_sizes_by_name(trash_folder))
+}
+
+/// Returns the size of each of the `items` like [`metadata`] does, but reads the
+/// `directorysizes` file of each trash folder only once.
+pub(crate) fn item_sizes(items: &[TrashItem]) -> Vec<Result<u64, Error>> {
+    let mut sizes_by_folder: HashMap<&Path, DirectorySizes> = HashMap::new();
+    items
+        .iter()
+        .map(|item| {
+            let (trash_folder, _) = trash_folder_and_name(item)?;
+            let sizes = sizes_by_folder
+                .entry(trash_folder)
+                .or_insert_with(|| directory_sizes_by_name(trash_folder));
+            metadata_with_sizes(item, sizes).map(|metadata| metadata.size)
+        })
+        .collect()
+}
+
+/// Returns the metadata of `item`, looking up its size in the `directorysizes` entries of its
+/// trash folder if it's a directory.
+fn metadata_with_sizes(
+    item: &TrashItem,
+    sizes: &DirectorySizes,
+) -> Result<TrashItemMetadata, Error> {
+    let (trash_folder, name_in_trash) = trash_folder_and_name(item)?;
+    let file = trash_folder.join("files").join(name_in_trash);
+    let metadata = file.symlink_metadata().map_err(|e| fsys_err(&file, e))?;
+    let file_type = metadata.file_type();
+    let kind = if file_type.is_dir() {
+        TrashItemKind::Directory
+    } else if file_type.is_symlink() {
+        TrashItemKind::Symlink
+    } else if file_type.is_file() {
+        TrashItemKind::File
+    } else {
+        TrashItemKind::Other
+    };
+    let size = if kind == TrashItemKind::Directory {
+        match cached_directory_size(trash_folder, name_in_trash, sizes) {
+            Some(size) => size,
+            None => disk_usage(&file).map_err(|e| fsys_err(&file, e))?,
+        }
+    } else {
+        // `st_blocks` is always in 512 byte units
+        metadata.blocks() * 512
+    };
+    Ok(TrashItemMetadata { kind, size })
+}
+
+pub(crate) fn purge_all<I>(ctx: &TrashContext, items: I) -> Result<(), Error>
 where
     I: IntoIterator<Item = TrashItem>,
 {
+    let mut tracker = ProgressTracker::new(ctx, TrashOperation::Purge);
     for item in items.into_iter() {
-        // When purging an item the "in-trash" filename must be parsed from the trashinfo filename
-        // which is the filename in the `id` field.
+        ctx.check_cancelled()?;
         let info_file = &item.id;
+        let (trash_folder, name_in_trash) = trash_folder_and_name(&item)?;
 
-        // A bunch of unwraps here. This is fine because if any of these fail that means
-        // that either there's a bug in this code or the target system didn't follow
-        // the specification.
-        let trash_folder = Path::new(info_file).parent().unwrap().parent().unwrap();
-        let name_in_trash = Path::new(info_file).file_stem().unwrap();
-
-        let file = trash_folder.join("files").join(&name_in_trash);
-        assert!(file.exists());
-        if file.is_dir() {
-            std::fs::remove_dir_all(&file).map_err(|e| fsys_err_to_unknown(&file, e))?;
-        // TODO Update directory size cache if there's one.
-        } else {
-            std::fs::remove_file(&file).map_err(|e| fsys_err_to_unknown(&file, e))?;
+        let file = trash_folder.join("files").join(name_in_trash);
+        match file.symlink_metadata() {
+            Ok(metadata) => {
+                let result = remove_recursively_tracked(&file, &mut tracker);
+                // Even a partially removed directory has a different size than the cached one.
+                if metadata.is_dir() {
+                    remove_directory_size(trash_folder, name_in_trash);
+                }
+                result.map_err(|e| fsys_or_cancelled(&file, e))?;
+            }
+            // The info file is orphaned, removing it is all that's left to do.
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                warn!("The trashed file {:?} doesn't exist, removing its info file only", file);
+            }
+            Err(e) => return Err(fsys_err(&file, e)),
         }
-        std::fs::remove_file(info_file).map_err(|e| fsys_err_to_unknown(&info_file, e))?;
+        std::fs::remove_file(info_file).map_err(|e| fsys_err(info_file, e))?;
+        tracker.item_done(&file);
     }
 
     Ok(())
 }
 
-pub fn restore_all<I>(items: I) -> Result<(), Error>
+pub(crate) fn empty(ctx: &TrashContext) -> Result<(), Error> {
+    for trash_folder in listed_trash_folders(ctx)? {
+        empty_trash_folder(&trash_folder)?;
+    }
+    Ok(())
+}
+
+/// Removes everything from the `files`, `info`, and `quarantine` folders of the trash folder,
+/// including the files without an info file and the info files without a file, as well as the
+/// `directorysizes` cache. The trash folder itself is kept.
+fn empty_trash_folder(trash_folder: &Path) -> Result<(), Error> {
+    let folders = ["files", "info", fsck::QUARANTINE_FOLDER].map(|name| trash_folder.join(name));
+    for folder in folders {
+        let read_dir = match std::fs::read_dir(&folder) {
+            Ok(read_dir) => read_dir,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(fsys_err(&folder, e)),
+        };
+        for entry in read_dir {
+            let path = entry.map_err(|e| fsys_err(&folder, e))?.path();
+            let metadata = match path.symlink_metadata() {
+                Ok(metadata) => metadata,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(fsys_err(&path, e)),
+            };
+            let result = if metadata.is_dir() {
+                std::fs::remove_dir_all(&path)
+            } else {
+                std::fs::remove_file(&path)
+            };
+            result.map_err(|e| fsys_err(&path, e))?;
+        }
+    }
+    let directory_sizes = trash_folder.join("directorysizes");
+    match std::fs::remove_file(&directory_sizes) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(fsys_err(&directory_sizes, e)),
+        _ => Ok(()),
+    }
+}
+
+pub(crate) fn restore_all<I>(ctx: &TrashContext, items: I) -> Result<(), Error>
+where
+    I: IntoIterator<Item = TrashItem>,
+{
+    let options = RestoreOptions { collision_policy: RestoreCollisionPolicy::Fail };
+    let mut outcomes = restore_all_with_options(ctx, items, &options)?.into_iter();
+    while let Some((item, outcome)) = outcomes.next() {
+        match outcome {
+            RestoreOutcome::Restored { .. } => {}
+            RestoreOutcome::Collision { path } => {
+                let remaining_items =
+                    std::iter::once(item).chain(outcomes.map(|(item, _)| item)).collect();
+                return Err(Error::RestoreCollision { path, remaining_items });
+            }
+            RestoreOutcome::Failed(error) => return Err(error),
+            RestoreOutcome::Skipped { .. } | RestoreOutcome::NotAttempted => {
+                unreachable!("{:?} with the `Fail` collision policy", outcome)
+            }
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn restore_all_with_options<I>(
+    ctx: &TrashContext,
+    items: I,
+    options: &RestoreOptions,
+) -> Result<Vec<(TrashItem, RestoreOutcome)>, Error>
 where
     I: IntoIterator<Item = TrashItem>,
 {
     // Simply read the items' original location from the infofile and attemp to move the items there
     // and delete the infofile if the move operation was sucessful.
 
+    let mut result = Vec::new();
     let mut iter = items.into_iter();
+    let mut tracker = ProgressTracker::new(ctx, TrashOperation::Restore);
     while let Some(item) = iter.next() {
-        // The "in-trash" filename must be parsed from the trashinfo filename
-        // which is the filename in the `id` field.
-        let info_file = &item.id;
+        let (outcome, abort) = match ctx.check_cancelled() {
+            Ok(()) => {
+                let outcome = restore_one(&item, options.collision_policy, &mut tracker);
+                // Only the `Fail` policy stops at the first item that couldn't be restored.
+                let abort = options.collision_policy == RestoreCollisionPolicy::Fail
+                    && matches!(
+                        outcome,
+                        RestoreOutcome::Collision { .. } | RestoreOutcome::Failed(_)
+                    );
+                (outcome, abort)
+            }
+            Err(error) => (RestoreOutcome::Failed(error), true),
+        };
+        result.push((item, outcome));
+        if abort {
+            result.extend(iter.map(|item| (item, RestoreOutcome::NotAttempted)));
+            break;
+        }
+    }
+    Ok(result)
+}
 
-        // A bunch of unwraps here. This is fine because if any of these fail that means
-        // that either there's a bug in this code or the target system didn't follow
-        // the specification.
-        let trash_folder = Path::new(info_file).parent().unwrap().parent().unwrap();
-        let name_in_trash = Path::new(info_file).file_stem().unwrap();
-
-        let file = trash_folder.join("files").join(&name_in_trash);
-        assert!(file.exists());
-        // TODO add option to forcefully replace any target at the restore location
-        // if it already exists.
-        let original_path = item.original_path();
-        // Make sure the parent exists so that `create_dir` doesn't faile due to that.
-        create_dir_all(&item.original_parent)
-            .map_err(|e| fsys_err_to_unknown(&item.original_parent, e))?;
-        let mut collision = false;
-        if file.is_dir() {
-            // NOTE create_dir_all succeeds when the path already exist but create_dir
-            // fails with `std::io::ErrorKind::AlreadyExists`.
-            if let Err(e) = std::fs::create_dir(&original_path) {
-                if e.kind() == std::io::ErrorKind::AlreadyExists {
-                    collision = true;
-                } else {
-                    return Err(fsys_err_to_unknown(&original_path, e));
+fn restore_one(
+    item: &TrashItem,
+    policy: RestoreCollisionPolicy,
+    tracker: &mut ProgressTracker,
+) -> RestoreOutcome {
+    let (trash_folder, name_in_trash) = match trash_folder_and_name(item) {
+        Ok(folder_and_name) => folder_and_name,
+        Err(e) => return RestoreOutcome::Failed(e),
+    };
+    let file = trash_folder.join("files").join(name_in_trash);
+    let is_dir = match file.symlink_metadata() {
+        Ok(metadata) => metadata.is_dir(),
+        Err(e) => return RestoreOutcome::Failed(fsys_err(&file, e)),
+    };
+    let original_path = item.original_path();
+    // Make sure the parent exists so that `create_dir` doesn't faile due to that.
+    if let Err(e) = create_dir_all(&item.original_parent) {
+        return RestoreOutcome::Failed(fsys_err(&item.original_parent, e));
+    }
+    let mut target = original_path.clone();
+    let mut attempt = 0;
+    // What was at the original location when overwriting, moved aside until the item is restored.
+    let mut overwritten: Option<PathBuf> = None;
+    loop {
+        match create_restore_placeholder(&target, is_dir) {
+            Ok(()) => break,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => match policy {
+                RestoreCollisionPolicy::Fail => {
+                    return RestoreOutcome::Collision { path: original_path }
+                }
+                RestoreCollisionPolicy::Skip => {
+                    return RestoreOutcome::Skipped { path: original_path }
+                }
+                // Something appeared again after moving the first one aside.
+                RestoreCollisionPolicy::Overwrite if overwritten.is_some() => {
+                    put_back_overwritten(overwritten.as_deref(), &target);
+                    return RestoreOutcome::Failed(fsys_err(&target, e));
+                }
+                RestoreCollisionPolicy::Overwrite => {
+                    let aside = overwritten_path(&target);
+                    if let Err(e) = std::fs::rename(&target, &aside) {
+                        return RestoreOutcome::Failed(fsys_err(&target, e));
+                    }
+                    overwritten = Some(aside);
+                }
+                RestoreCollisionPolicy::Rename => {
+                    attempt += 1;
+                    target = renamed_restore_path(&original_path, is_dir, attempt);
                 }
+            },
+            Err(e) => {
+                put_back_overwritten(overwritten.as_deref(), &target);
+                return RestoreOutcome::Failed(fsys_err(&target, e));
             }
-        } else {
-            // File or symlink
-            if let Err(e) = OpenOptions::new().create_new(true).write(true).open(&original_path) {
-                if e.kind() == std::io::ErrorKind::AlreadyExists {
-                    collision = true;
-                } else {
-                    return Err(fsys_err_to_unknown(&original_path, e));
+        }
+    }
+    match move_out_of_trash(item, &target, is_dir, tracker) {
+        Ok(()) => {
+            if let Some(aside) = &overwritten {
+                if let Err(e) = remove_recursively(aside) {
+                    warn!(
+                        "Failed to remove {:?}, which was overwritten by {:?}: {}",
+                        aside, target, e
+                    );
                 }
             }
+            tracker.item_done(&target);
+            RestoreOutcome::Restored { path: target }
         }
-        if collision {
-            let remaining: Vec<_> = std::iter::once(item).chain(iter).collect();
-            return Err(Error::RestoreCollision {
-                path: original_path,
-                remaining_items: remaining,
-            });
+        Err(error) => {
+            put_back_overwritten(overwritten.as_deref(), &target);
+            RestoreOutcome::Failed(error)
+        }
+    }
+}
+
+/// Moves what was overwritten back to `target` after restoring an item over it failed. It's left
+/// where it is if something took `target` in the meantime.
+fn put_back_overwritten(overwritten: Option<&Path>, target: &Path) {
+    let aside = match overwritten {
+        Some(aside) => aside,
+        None => return,
+    };
+    let result = match target.symlink_metadata() {
+        // The placeholder of the item may still be there.
+        Ok(_) => remove_restore_placeholder(target).and_then(|_| std::fs::rename(aside, target)),
+        Err(_) => std::fs::rename(aside, target),
+    };
+    if let Err(e) = result {
+        warn!(
+            "Failed to move {:?} back to {:?}, it's left there. The error was: {}",
+            aside, target, e
+        );
+    }
+}
+
+/// Removes `target` only if it's an empty file or directory, like the placeholder of an item.
+fn remove_restore_placeholder(target: &Path) -> Result<(), io::Error> {
+    let metadata = target.symlink_metadata()?;
+    if metadata.is_dir() {
+        // Fails if the directory isn't empty.
+        std::fs::remove_dir(target)
+    } else if metadata.is_file() && metadata.len() == 0 {
+        std::fs::remove_file(target)
+    } else {
+        Err(io::Error::new(io::ErrorKind::AlreadyExists, "The path was taken in the meantime"))
+    }
+}
+
+pub(crate) fn restore_to(
+    ctx: &TrashContext,
+    item: TrashItem,
+    destination: &Path,
+    new_name: Option<&OsStr>,
+) -> Result<PathBuf, Error> {
+    let (trash_folder, name_in_trash) = trash_folder_and_name(&item)?;
+    let file = trash_folder.join("files").join(name_in_trash);
+    let is_dir = file.symlink_metadata().map_err(|e| fsys_err(&file, e))?.is_dir();
+    create_dir_all(destination).map_err(|e| fsys_err(destination, e))?;
+    let target = destination.join(new_name.unwrap_or(&item.name));
+    if let Err(e) = create_restore_placeholder(&target, is_dir) {
+        if e.kind() == io::ErrorKind::AlreadyExists {
+            return Err(Error::RestoreCollision { path: target, remaining_items: vec![item] });
+        }
+        return Err(fsys_err(&target, e));
+    }
+    let mut tracker = ProgressTracker::new(ctx, TrashOperation::Restore);
+    move_out_of_trash(&item, &target, is_dir, &mut tracker)?;
+    tracker.item_done(&target);
+    Ok(target)
+}
+
+/// Reserves `target` for an item that's being restored. The item is then renamed over it.
+fn create_restore_placeholder(target: &Path, is_dir: bool) -> Result<(), io::Error> {
+    if is_dir {
+        // NOTE create_dir_all succeeds when the path already exist but create_dir
+        // fails with `std::io::ErrorKind::AlreadyExists`.
+        std::fs::create_dir(target)
+    } else {
+        // File or symlink
+        OpenOptions::new().create_new(true).write(true).open(target).map(|_| ())
+    }
+}
+
+/// Moves the payload of `item` over the placeholder at `target` and removes the info file.
+/// Falls back to copying when `target` is on a different device than the trash.
+fn move_out_of_trash(
+    item: &TrashItem,
+    target: &Path,
+    is_dir: bool,
+    tracker: &mut ProgressTracker,
+) -> Result<(), Error> {
+    let info_file = Path::new(&item.id);
+    let (trash_folder, name_in_trash) = trash_folder_and_name(item)?;
+    let file = trash_folder.join("files").join(name_in_trash);
+    if let Err(error) = std::fs::rename(&file, target) {
+        if let Err(e) = remove_recursively(target) {
+            warn!("Failed to remove the placeholder at {:?}: {}", target, e);
         }
-        std::fs::rename(&file, &original_path).map_err(|e| fsys_err_to_unknown(&file, e))?;
-        std::fs::remove_file(info_file).map_err(|e| fsys_err_to_unknown(&info_file, e))?;
+        if error.raw_os_error() != Some(libc::EXDEV) {
+            return Err(fsys_err(&file, error));
+        }
+        copy_out_of_trash(&file, target, tracker)?;
+    }
+    std::fs::remove_file(info_file).map_err(|e| fsys_err(info_file, e))?;
+    if is_dir {
+        remove_directory_size(trash_folder, name_in_trash);
+    }
+    Ok(())
+}
+
+/// The counterpart of `copy_to_trash_and_remove` for restoring `file` to another device.
+fn copy_out_of_trash(
+    file: &Path,
+    target: &Path,
+    tracker: &mut ProgressTracker,
+) -> Result<(), Error> {
+    let copy_result = copy_recursively(file, target, tracker);
+    let roll_back_target = || {
+        if let Err(e) = remove_recursively(target) {
+            warn!("Failed to remove the partial copy {:?}. The error was: {:?}", target, e);
+        }
+    };
+    if let Err(error) = copy_result {
+        roll_back_target();
+        return Err(fsys_or_cancelled(file, error));
+    }
+    if let Err(error) = remove_recursively(file) {
+        // Some entries of `file` may have been removed by now. Put them back in place.
+        if let Err(rollback_err) = copy_missing(target, file) {
+            error!("Failed to remove {:?} after copying it out of the trash, then failed to copy the removed entries back. The item is kept at {:?}. The error was: {:?}", file, target, rollback_err);
+        } else {
+            roll_back_target();
+        }
+        return Err(fsys_err(file, error));
     }
     Ok(())
 }
@@ -331,7 +1328,11 @@ fn execute_on_mounted_trash_folders<F: FnMut(PathBuf) -> Result<(), Error>>(
     let should_execute;
     if !trash_path.exists() || !trash_path.is_dir() {
         if create_folder {
-            std::fs::create_dir(&trash_path).map_err(|e| fsys_err_to_unknown(&trash_path, e))?;
+            // Other users must not be able to see what's in this user's trash.
+            std::fs::DirBuilder::new()
+                .mode(0o700)
+                .create(&trash_path)
+                .map_err(|e| fsys_err(&trash_path, e))?;
             should_execute = true;
         } else {
             should_execute = false;
@@ -345,11 +1346,44 @@ fn execute_on_mounted_trash_folders<F: FnMut(PathBuf) -> Result<(), Error>>(
     Ok(())
 }
 
+/// Returns the trash folder that belongs to the partition mounted at `topdir`. The folder and its
+/// subfolders are created if they don't exist and `create` is true. Otherwise the folder that
+/// would be created is returned if `topdir` allows creating it.
+fn mounted_trash_folder(uid: u32, topdir: &Path, create: bool) -> Result<PathBuf, Error> {
+    let mut result = None;
+    execute_on_mounted_trash_folders(uid, topdir, true, create, |trash_path| {
+        if create {
+            prepare_trash_folder(&trash_path)?;
+        }
+        result = Some(trash_path);
+        Ok(())
+    })?;
+    match result {
+        Some(trash_path) => Ok(trash_path),
+        // `op` is always called when `create_folder` is true, unless an error occured.
+        None => {
+            check_writable(topdir)?;
+            Ok(topdir.join(format!(".Trash-{}", uid)))
+        }
+    }
+}
+
+/// Ensures the `files` and `info` folders exist
+fn prepare_trash_folder(trash_folder: &Path) -> Result<(), Error> {
+    let files_folder = trash_folder.join("files");
+    let info_folder = trash_folder.join("info");
+    create_dir_all(&files_folder).map_err(|e| fsys_err(&files_folder, e))?;
+    create_dir_all(&info_folder).map_err(|e| fsys_err(&info_folder, e))?;
+    Ok(())
+}
+
 fn move_to_trash(
+    ctx: &TrashContext,
     src: impl AsRef<Path>,
     trash_folder: impl AsRef<Path>,
     topdir: impl AsRef<Path>,
-) -> Result<(), Error> {
+    tracker: &mut ProgressTracker,
+) -> Result<TrashItem, Error> {
     let src = src.as_ref();
     let trash_folder = trash_folder.as_ref();
     let topdir = topdir.as_ref();
@@ -357,9 +1391,7 @@ fn move_to_trash(
     let files_folder = trash_folder.join("files");
     let info_folder = trash_folder.join("info");
 
-    // Ensure the `files` and `info` folders exist
-    create_dir_all(&files_folder).map_err(|e| fsys_err_to_unknown(&files_folder, e))?;
-    create_dir_all(&info_folder).map_err(|e| fsys_err_to_unknown(&info_folder, e))?;
+    prepare_trash_folder(trash_folder)?;
 
     // This kind of validity must only apply ot administrator style trash folders
     // See Trash directories, (1) at https://specifications.freedesktop.org/trash-spec/trashspec-1.0.html
@@ -377,50 +1409,51 @@ fn move_to_trash(
     // using the `rename` function.
     let filename = src.file_name().unwrap();
     let mut appendage = 0;
-    loop {
-        use std::io;
+    let item = loop {
         appendage += 1;
-        let in_trash_name = if appendage > 1 {
-            format!("{}.{}", filename.to_str().unwrap(), appendage)
-        } else {
-            filename.to_str().unwrap().into()
-        };
-        let info_name = format!("{}.trashinfo", in_trash_name);
+        let mut in_trash_name = filename.to_owned();
+        if appendage > 1 {
+            in_trash_name.push(format!(".{}", appendage));
+        }
+        let mut info_name = in_trash_name.clone();
+        info_name.push(".trashinfo");
         let info_file_path = info_folder.join(&info_name);
         let info_result = OpenOptions::new().create_new(true).write(true).open(&info_file_path);
-        match info_result {
+        let deletion_time = match info_result {
             Err(error) => {
                 if error.kind() == io::ErrorKind::AlreadyExists {
                     continue;
                 } else {
                     debug!("Failed to create the new file {:?}", info_file_path);
-                    return Err(fsys_err_to_unknown(info_file_path, error));
+                    return Err(fsys_err(info_file_path, error));
                 }
             }
             Ok(mut file) => {
                 debug!("Successfully created {:?}", info_file_path);
                 // Write the info file before actually moving anything
                 let now = chrono::Local::now();
-                writeln!(file, "[Trash Info]")
-                    .and_then(|_| {
-                        let absolute_uri = encode_uri_path(src);
-                        let topdir_uri = encode_uri_path(topdir);
-                        let relative_untrimmed = absolute_uri
-                            .chars()
-                            .skip(topdir_uri.chars().count())
-                            .collect::<String>();
-                        let relative_uri = relative_untrimmed.trim_start_matches('/');
-                        let path =
-                            if topdir == root { absolute_uri.as_str() } else { relative_uri };
-                        writeln!(file, "Path={}", path).and_then(|_| {
-                            writeln!(file, "DeletionDate={}", now.format("%Y-%m-%dT%H:%M:%S"))
-                        })
-                    })
-                    .map_err(|e| fsys_err_to_unknown(&info_file_path, e))?;
+                let path = match src.strip_prefix(topdir) {
+                    Ok(relative) if topdir != root => relative,
+                    _ => src,
+                };
+                let info = TrashInfo { path: path.to_owned(), deletion_date: now.naive_local() };
+                file.write_all(info.to_string().as_bytes())
+                    .map_err(|e| fsys_err(&info_file_path, e))?;
+                now
             }
-        }
+        };
         let path = files_folder.join(&in_trash_name);
-        match move_items_no_replace(src, &path) {
+        let mut move_result = move_items_no_replace(src, &path);
+        if let Err(error) = &move_result {
+            if error.raw_os_error() == Some(libc::EXDEV)
+                && ctx.platform_specific.copies_across_devices()
+            {
+                debug!("The trash is on a different device than {:?}, so copying it.", src);
+                copy_to_trash_and_remove(src, &path, &info_file_path, tracker)?;
+                move_result = Ok(());
+            }
+        }
+        match move_result {
             Err(error) => {
                 debug!("Failed moving item to the trash (this is usually OK). {:?}", error);
                 // Try to delete the info file
@@ -430,112 +1463,676 @@ fn move_to_trash(
                 if error.kind() == io::ErrorKind::AlreadyExists {
                     continue;
                 } else {
-                    return Err(fsys_err_to_unknown(path, error));
+                    return Err(fsys_err(src, error));
                 }
             }
             Ok(_) => {
                 // We did it!
-                break;
+                if path.symlink_metadata().map(|m| m.is_dir()).unwrap_or(false) {
+                    add_directory_size(trash_folder, &in_trash_name, &info_file_path);
+                }
+                break TrashItem {
+                    id: info_file_path.into(),
+                    name: filename.to_owned(),
+                    original_parent: src.parent().unwrap().into(),
+                    time_deleted: deletion_time.timestamp(),
+                };
             }
         }
+    };
+
+    Ok(item)
+}
+
+/// An error may mean that a collision was found.
+fn move_items_no_replace(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> Result<(), io::Error> {
+    let src = src.as_ref();
+    let dst = dst.as_ref();
+
+    try_creating_placeholders(src, dst)?;
+
+    // All placeholders are in place. LET'S OVERWRITE
+    // Note that `rename` replaces the placeholder be it an empty file or an empty directory.
+    if let Err(error) = std::fs::rename(src, dst) {
+        // The placeholder was created by us so it's safe to remove it.
+        if let Err(placeholder_err) = remove_recursively(dst) {
+            warn!(
+                "Failed to remove the placeholder {:?}. The error was: {:?}",
+                dst, placeholder_err
+            );
+        }
+        return Err(error);
     }
+    Ok(())
+}
+
+/// Copies `src` to `dst` inside the trash and then removes `src`. This is used when `src` can't be
+/// renamed into the trash because they are on different devices. The `info_file` is only removed
+/// when the item doesn't end up in the trash.
+fn copy_to_trash_and_remove(
+    src: &Path,
+    dst: &Path,
+    info_file: &Path,
+    tracker: &mut ProgressTracker,
+) -> Result<(), Error> {
+    let roll_back_trash = || {
+        if let Err(e) = remove_recursively(dst) {
+            warn!(
+                "Failed to remove the partial copy {:?} from the trash. The error was: {:?}",
+                dst, e
+            );
+        }
+        if let Err(e) = std::fs::remove_file(info_file) {
+            warn!("Failed to remove the info file {:?}. The error was: {:?}", info_file, e);
+        }
+    };
 
+    // The placeholder was already removed by `move_items_no_replace`, however the name is still
+    // reserved by the info file.
+    let copy_result = copy_recursively(src, dst, tracker);
+    if let Err(error) = copy_result {
+        roll_back_trash();
+        return Err(fsys_or_cancelled(src, error));
+    }
+    if let Err(error) = remove_recursively(src) {
+        // Some entries of `src` may have been removed by now. Put them back in place.
+        if let Err(rollback_err) = copy_missing(dst, src) {
+            error!("Failed to remove {:?} after copying it into the trash, then failed to copy the removed entries back. The item is kept in the trash at {:?}. The error was: {:?}", src, dst, rollback_err);
+        } else {
+            roll_back_trash();
+        }
+        return Err(fsys_err(src, error));
+    }
     Ok(())
 }
 
-fn execute_src_to_dst_operation<S1, D1>(
-    src: S1,
-    dst: D1,
-    dir: &'static dyn Fn(&Path) -> Result<(), std::io::Error>,
-    file: &'static dyn Fn(&Path, &Path) -> Result<(), std::io::Error>,
-) -> Result<(), std::io::Error>
-where
-    S1: AsRef<Path>,
-    D1: AsRef<Path>,
-{
+/// Copies `src` to `dst` without following symbolic links. Every file, directory, and symbolic
+/// link that was copied is reported to the `tracker`.
+fn copy_recursively(
+    src: &Path,
+    dst: &Path,
+    tracker: &mut ProgressTracker,
+) -> Result<(), io::Error> {
+    tracker.check_cancelled()?;
+    let metadata = src.symlink_metadata()?;
+    let file_type = metadata.file_type();
+    if file_type.is_dir() {
+        std::fs::create_dir(dst)?;
+        for entry in std::fs::read_dir(src)? {
+            // Forward the error because it's not okay if something is happening
+            // to the files while we are trying to copy them.
+            let entry = entry?;
+            copy_recursively(&entry.path(), &dst.join(entry.file_name()), tracker)?;
+        }
+        // Setting the permissions last, in case the folder is read-only.
+        std::fs::set_permissions(dst, metadata.permissions())?;
+        tracker.entry_done(src, 0);
+    } else if file_type.is_symlink() {
+        symlink(std::fs::read_link(src)?, dst)?;
+        tracker.entry_done(src, 0);
+    } else if file_type.is_file() {
+        let mut src_file = File::open(src)?;
+        let mut dst_file = OpenOptions::new().create_new(true).write(true).open(dst)?;
+        let bytes = io::copy(&mut src_file, &mut dst_file)?;
+        dst_file.set_permissions(metadata.permissions())?;
+        dst_file.set_modified(metadata.modified()?)?;
+        tracker.entry_done(src, bytes);
+    } else {
+        return Err(io::Error::other(format!(
+            "Copying special files is not supported. Path: {:?}",
+            src
+        )));
+    }
+    Ok(())
+}
+
+/// Copies the entries of `src` that don't exist at `dst`.
+fn copy_missing(src: &Path, dst: &Path) -> Result<(), io::Error> {
+    match dst.symlink_metadata() {
+        Ok(dst_metadata) => {
+            if dst_metadata.is_dir() && src.symlink_metadata()?.is_dir() {
+                for entry in std::fs::read_dir(src)? {
+                    let entry = entry?;
+                    copy_missing(&entry.path(), &dst.join(entry.file_name()))?;
+                }
+            }
+            Ok(())
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            copy_recursively(src, dst, &mut ProgressTracker::silent(TrashOperation::Restore))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Removes `path` whether it's a directory or not. Symbolic links are not followed.
+fn remove_recursively(path: &Path) -> Result<(), io::Error> {
+    if path.symlink_metadata()?.is_dir() {
+        std::fs::remove_dir_all(path)
+    } else {
+        std::fs::remove_file(path)
+    }
+}
+
+/// Like `remove_recursively`, but reports every file, directory, and symbolic link that was
+/// removed to the `tracker`.
+fn remove_recursively_tracked(path: &Path, tracker: &mut ProgressTracker) -> Result<(), io::Error> {
+    tracker.check_cancelled()?;
+    let metadata = path.symlink_metadata()?;
+    if metadata.is_dir() {
+        for entry in std::fs::read_dir(path)? {
+            remove_recursively_tracked(&entry?.path(), tracker)?;
+        }
+        std::fs::remove_dir(path)?;
+        tracker.entry_done(path, 0);
+    } else {
+        std::fs::remove_file(path)?;
+        tracker.entry_done(path, metadata.len());
+    }
+    Ok(())
+}
+
+/// Counts what an operation processed and reports it to the progress observer of the context.
+/// It also checks the cancellation token of the context for the functions that process the
+/// entries of an item one by one.
+struct ProgressTracker<'a> {
+    /// `None` for operations that can't be cancelled and don't report progress, like rollbacks.
+    ctx: Option<&'a TrashContext>,
+    operation: TrashOperation,
+    items_processed: u64,
+    entries_processed: u64,
+    bytes_processed: u64,
+}
+impl<'a> ProgressTracker<'a> {
+    fn new(ctx: &'a TrashContext, operation: TrashOperation) -> Self {
+        ProgressTracker {
+            ctx: Some(ctx),
+            operation,
+            items_processed: 0,
+            entries_processed: 0,
+            bytes_processed: 0,
+        }
+    }
+
+    fn silent(operation: TrashOperation) -> Self {
+        ProgressTracker {
+            ctx: None,
+            operation,
+            items_processed: 0,
+            entries_processed: 0,
+            bytes_processed: 0,
+        }
+    }
+
+    fn report(&self, current_path: &Path) {
+        if let Some(ctx) = self.ctx {
+            ctx.report_progress(&Progress {
+                operation: self.operation,
+                items_processed: self.items_processed,
+                entries_processed: self.entries_processed,
+                bytes_processed: self.bytes_processed,
+                current_path,
+            });
+        }
+    }
+
+    fn entry_done(&mut self, path: &Path, bytes: u64) {
+        self.entries_processed += 1;
+        self.bytes_processed += bytes;
+        self.report(path);
+    }
+
+    fn item_done(&mut self, path: &Path) {
+        self.items_processed += 1;
+        self.report(path);
+    }
+
+    /// Returns an error that `fsys_or_cancelled` turns into `Error::Cancelled` if the operation
+    /// was cancelled.
+    fn check_cancelled(&self) -> Result<(), io::Error> {
+        match self.ctx.map(TrashContext::check_cancelled) {
+            Some(Err(_)) => Err(io::Error::new(io::ErrorKind::Interrupted, Cancelled)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The source of the `io::Error` that `ProgressTracker::check_cancelled` returns.
+#[derive(Debug)]
+struct Cancelled;
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The operation was cancelled")
+    }
+}
+impl std::error::Error for Cancelled {}
+
+fn fsys_or_cancelled(path: impl AsRef<Path>, source: io::Error) -> Error {
+    if source.get_ref().is_some_and(|e| e.is::<Cancelled>()) {
+        Error::Cancelled
+    } else {
+        fsys_err(path, source)
+    }
+}
+
+fn try_creating_placeholders(
+    src: impl AsRef<Path>,
+    dst: impl AsRef<Path>,
+) -> Result<(), std::io::Error> {
     let src = src.as_ref();
     let dst = dst.as_ref();
+    let metadata = src.symlink_metadata()?;
+    if metadata.is_dir() {
+        // NOTE create_dir fails if the directory already exists
+        std::fs::create_dir(dst)?;
+    } else {
+        // Symlink or file
+        OpenOptions::new().create_new(true).write(true).open(dst)?;
+    }
+    Ok(())
+}
+
+/// Returns the directory that the relative `Path` keys of the info files in `trash_folder` are
+/// relative to. This is `$topdir` for both `$topdir/.Trash/$uid` and `$topdir/.Trash-$uid`.
+fn trash_folder_topdir(trash_folder: &Path) -> &Path {
+    let parent = trash_folder.parent().unwrap_or(trash_folder);
+    if parent.file_name() == Some(OsStr::new(".Trash")) {
+        parent.parent().unwrap_or(parent)
+    } else {
+        parent
+    }
+}
+
+/// The contents of a `.trashinfo` file, which describes a single item in the trash.
+///
+/// The file follows the format of desktop entry files: it has a `[Trash Info]` group with a
+/// percent-encoded `Path` key and a `DeletionDate` key. Comments, blank lines, unknown keys, and
+/// other groups are ignored when parsing.
+///
+/// # Example
+///
+/// ```
+/// use trash::freedesktop::TrashInfo;
+/// let info = TrashInfo::parse("[Trash Info]\nPath=/home/user/a%3Db.txt\nDeletionDate=2021-05-02T10:20:30\n").unwrap();
+/// assert_eq!(info.path, std::path::Path::new("/home/user/a=b.txt"));
+/// assert_eq!(TrashInfo::parse(&info.to_string()).unwrap(), info);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrashInfo {
+    /// The original location of the item. It's either absolute or relative to the topdir of the
+    /// trash folder that contains the info file.
+    pub path: PathBuf,
+
+    /// The time the item was deleted, in the local time zone.
+    pub deletion_date: NaiveDateTime,
+}
+impl TrashInfo {
+    const GROUP: &'static str = "[Trash Info]";
+    const DATE_FORMAT: &'static str = "%Y-%m-%dT%H:%M:%S";
+
+    /// Parses the contents of a `.trashinfo` file.
+    pub fn parse(contents: &str) -> Result<TrashInfo, Error> {
+        let invalid =
+            |reason: &str| Error::InvalidTrashInfo { path: PathBuf::new(), reason: reason.into() };
+        let mut lines = contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'));
+        if lines.next() != Some(Self::GROUP) {
+            return Err(invalid("the first group is not [Trash Info]"));
+        }
+        let mut path = None;
+        let mut deletion_date = None;
+        for line in lines {
+            if line.starts_with('[') {
+                // Keys of other groups are not ours to interpret.
+                break;
+            }
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim_end(), unescape_value(value.trim_start())),
+                None => {
+                    return Err(invalid(&format!("the line '{}' is not a key-value pair", line)))
+                }
+            };
+            match key {
+                "Path" if path.is_none() => path = Some(value),
+                "DeletionDate" if deletion_date.is_none() => deletion_date = Some(value),
+                _ => {}
+            }
+        }
+        let path = path.filter(|path| !path.is_empty()).ok_or_else(|| invalid("missing Path"))?;
+        let deletion_date = deletion_date.ok_or_else(|| invalid("missing DeletionDate"))?;
+        let deletion_date = NaiveDateTime::parse_from_str(&deletion_date, Self::DATE_FORMAT)
+            .map_err(|e| invalid(&format!("the DeletionDate '{}' is {}", deletion_date, e)))?;
+        Ok(TrashInfo { path: OsString::from_vec(percent_decode(&path)).into(), deletion_date })
+    }
+
+    /// Reads and parses the `.trashinfo` file at `path`.
+    pub fn read(path: impl AsRef<Path>) -> Result<TrashInfo, Error> {
+        let path = path.as_ref();
+        let contents = std::fs::read(path).map_err(|e| fsys_err(path, e))?;
+        let contents = String::from_utf8(contents).map_err(|_| Error::InvalidTrashInfo {
+            path: path.to_owned(),
+            reason: "it's not valid UTF-8".into(),
+        })?;
+        Self::parse(&contents).map_err(|error| match error {
+            Error::InvalidTrashInfo { reason, .. } => {
+                Error::InvalidTrashInfo { path: path.to_owned(), reason }
+            }
+            error => error,
+        })
+    }
+
+    /// Returns the deletion date as the number of non-leap seconds since the UNIX Epoch, or `None`
+    /// if the date doesn't exist in the local time zone.
+    pub fn time_deleted(&self) -> Option<i64> {
+        chrono::Local.from_local_datetime(&self.deletion_date).earliest().map(|t| t.timestamp())
+    }
+}
+impl fmt::Display for TrashInfo {
+    /// Formats the contents of a `.trashinfo` file.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", Self::GROUP)?;
+        writeln!(f, "Path={}", percent_encode(self.path.as_os_str().as_bytes()))?;
+        writeln!(f, "DeletionDate={}", self.deletion_date.format(Self::DATE_FORMAT))
+    }
+}
+
+/// Resolves the escape sequences of a desktop entry string value.
+fn unescape_value(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('s') => result.push(' '),
+            Some('n') => result.push('\n'),
+            Some('t') => result.push('\t'),
+            Some('r') => result.push('\r'),
+            Some(other) => result.push(other),
+            None => result.push('\\'),
+        }
+    }
+    result
+}
+
+/// An entry of the `directorysizes` file of a trash folder.
+///
+/// The `directorysizes` file caches the size of the directories in the trash so that file
+/// managers don't need to traverse them to display the size of the trash. See the "Directory size
+/// cache" section of the specification.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DirectorySize {
+    /// The name of the directory inside the `files` folder of the trash.
+    pub name: OsString,
+
+    /// The disk space used by the directory and its contents in bytes, calculated the same way as
+    /// `du -B1` does.
+    pub size: u64,
+
+    /// The modification time of the info file of the directory at the time the size was
+    /// calculated, in seconds since the UNIX Epoch. If this doesn't match the current
+    /// modification time of the info file, the entry is outdated.
+    pub mtime: i64,
+}
+
+/// Reads the `directorysizes` file of `trash_folder`, for example `~/.local/share/Trash`.
+///
+/// Returns an empty list if the file doesn't exist. Malformed lines are skipped.
+pub fn read_directory_sizes(trash_folder: impl AsRef<Path>) -> Result<Vec<DirectorySize>, Error> {
+    let path = trash_folder.as_ref().join("directorysizes");
+    let file = match File::open(&path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(fsys_err(&path, e)),
+    };
+    let mut result = Vec::new();
+    for line in BufReader::new(file).split(b'\n') {
+        let line = line.map_err(|e| fsys_err(&path, e))?;
+        match std::str::from_utf8(&line).ok().and_then(parse_directory_size) {
+            Some(entry) => result.push(entry),
+            None => {
+                debug!(
+                    "Skipping the malformed line '{}' in {:?}",
+                    String::from_utf8_lossy(&line),
+                    path
+                )
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// The entries of a `directorysizes` file by the name of their directory.
+type DirectorySizes = HashMap<OsString, DirectorySize>;
+
+/// Reads the `directorysizes` file of `trash_folder` so that the sizes of its directories can be
+/// looked up with [`cached_directory_size`]. A file that can't be read is treated like an empty
+/// one, because the cache is optional.
+fn directory_sizes_by_name(trash_folder: &Path) -> DirectorySizes {
+    match read_directory_sizes(trash_folder) {
+        Ok(entries) => entries.into_iter().map(|entry| (entry.name.clone(), entry)).collect(),
+        Err(error) => {
+            debug!("Ignoring the directorysizes of {:?}: {}", trash_folder, error);
+            HashMap::new()
+        }
+    }
+}
+
+/// Returns the cached size of the trashed directory `name_in_trash`, or `None` if there's no
+/// entry for it in `sizes` or the entry is outdated.
+fn cached_directory_size(
+    trash_folder: &Path,
+    name_in_trash: &OsStr,
+    sizes: &DirectorySizes,
+) -> Option<u64> {
+    let entry = sizes.get(name_in_trash)?;
+    let mut info_file_name = name_in_trash.to_owned();
+    info_file_name.push(".trashinfo");
+    let info_mtime = trash_folder.join("info").join(info_file_name).metadata().ok()?.mtime();
+    Some(entry).filter(|entry| entry.mtime == info_mtime).map(|entry| entry.size)
+}
+
+fn parse_directory_size(line: &str) -> Option<DirectorySize> {
+    let mut split = line.splitn(3, ' ');
+    let size = split.next()?.parse().ok()?;
+    let mtime = split.next()?.parse().ok()?;
+    let name = OsString::from_vec(percent_decode(split.next()?));
+    Some(DirectorySize { name, size, mtime })
+}
+
+/// Adds an entry for the directory that was just put into the trash. Failing to do so is not
+/// considered an error because the cache is optional.
+fn add_directory_size(trash_folder: &Path, name_in_trash: &OsStr, info_file: &Path) {
+    let dir = trash_folder.join("files").join(name_in_trash);
+    let result = info_file.metadata().map_err(|e| fsys_err(info_file, e)).and_then(|info| {
+        let size = disk_usage(&dir).map_err(|e| fsys_err(&dir, e))?;
+        update_directory_sizes(trash_folder, |entries| {
+            entries.retain(|e| e.name != name_in_trash);
+            entries.push(DirectorySize {
+                name: name_in_trash.to_owned(),
+                size,
+                mtime: info.mtime(),
+            });
+        })
+    });
+    if let Err(e) = result {
+        warn!(
+            "Failed to update the directory size cache of {:?}. The error was: {:?}",
+            trash_folder, e
+        );
+    }
+}
+
+/// Removes the entry of a directory that was removed from the trash. Failing to do so is not
+/// considered an error because outdated entries are recognized by their `mtime`.
+fn remove_directory_size(trash_folder: &Path, name_in_trash: &OsStr) {
+    if !trash_folder.join("directorysizes").exists() {
+        return;
+    }
+    let result = update_directory_sizes(trash_folder, |entries| {
+        entries.retain(|e| e.name != name_in_trash);
+    });
+    if let Err(e) = result {
+        warn!(
+            "Failed to update the directory size cache of {:?}. The error was: {:?}",
+            trash_folder, e
+        );
+    }
+}
+
+/// Rewrites the `directorysizes` file with the entries modified by `op`.
+///
+/// As the specification requires, the new content is written to a temporary file first which
+/// then atomically replaces the original. The trash folder is locked with `flock` meanwhile, so
+/// that the entries added by other processes that lock it too are not lost.
+fn update_directory_sizes(
+    trash_folder: &Path,
+    op: impl FnOnce(&mut Vec<DirectorySize>),
+) -> Result<(), Error> {
+    static TEMP_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
 
-    let metadata = src.symlink_metadata()?;
-    if metadata.is_dir() {
-        dir(dst)?;
-        let dir_entries = std::fs::read_dir(src)?;
-        for entry in dir_entries {
-            // Forward the error because it's not okay if something is happening
-            // to the files while we are trying to move them.
-            let entry = entry?;
-            let entry_src = entry.path();
-            let entry_dst = dst.join(entry.file_name());
-            execute_src_to_dst_operation(entry_src, entry_dst, dir, file)?;
+    // The lock is released when `lock` is closed.
+    let lock = File::open(trash_folder).map_err(|e| fsys_err(trash_folder, e))?;
+    if unsafe { libc::flock(lock.as_raw_fd(), libc::LOCK_EX) } != 0 {
+        return Err(fsys_err(trash_folder, io::Error::last_os_error()));
+    }
+    let mut entries = read_directory_sizes(trash_folder)?;
+    op(&mut entries);
+    let path = trash_folder.join("directorysizes");
+    let temp_path = trash_folder.join(format!(
+        "directorysizes.{}.{}",
+        std::process::id(),
+        TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let write_result = File::create(&temp_path).and_then(|mut file| {
+        for entry in entries.iter() {
+            writeln!(
+                file,
+                "{} {} {}",
+                entry.size,
+                entry.mtime,
+                percent_encode(entry.name.as_bytes())
+            )?;
         }
-    } else {
-        // Symlink or file
-        file(&src, &dst)?;
+        file.sync_all()
+    });
+    if let Err(e) = write_result.and_then(|_| std::fs::rename(&temp_path, &path)) {
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(fsys_err(&path, e));
     }
     Ok(())
 }
 
-/// An error may mean that a collision was found.
-fn move_items_no_replace(
-    src: impl AsRef<Path>,
-    dst: impl AsRef<Path>,
-) -> Result<(), std::io::Error> {
-    let src = src.as_ref();
-    let dst = dst.as_ref();
-
-    try_creating_placeholders(src, dst)?;
-
-    // All placeholders are in place. LET'S OVERWRITE
-    execute_src_to_dst_operation(src, dst, &|_| Ok(()), &|src, dst| std::fs::rename(src, dst))?;
-
-    // Once everything is moved, lets recursively remove the directory
-    if src.is_dir() {
-        std::fs::remove_dir_all(src)?;
+/// Returns the disk space used by `path` and everything in it, in bytes. Symbolic links are not
+/// followed and hard links are only counted once.
+fn disk_usage(path: &Path) -> Result<u64, io::Error> {
+    fn visit(path: &Path, seen: &mut HashSet<(u64, u64)>) -> Result<u64, io::Error> {
+        let metadata = path.symlink_metadata()?;
+        if metadata.nlink() > 1
+            && !metadata.is_dir()
+            && !seen.insert((metadata.dev(), metadata.ino()))
+        {
+            return Ok(0);
+        }
+        // `st_blocks` is always in 512 byte units
+        let mut size = metadata.blocks() * 512;
+        if metadata.is_dir() {
+            for entry in std::fs::read_dir(path)? {
+                size += visit(&entry?.path(), seen)?;
+            }
+        }
+        Ok(size)
     }
-    Ok(())
+    visit(path, &mut HashSet::new())
 }
 
-fn try_creating_placeholders(
-    src: impl AsRef<Path>,
-    dst: impl AsRef<Path>,
-) -> Result<(), std::io::Error> {
-    let src = src.as_ref();
-    let dst = dst.as_ref();
-    let metadata = src.symlink_metadata()?;
-    if metadata.is_dir() {
-        // NOTE create_dir fails if the directory already exists
-        std::fs::create_dir(dst)?;
-    } else {
-        // Symlink or file
-        OpenOptions::new().create_new(true).write(true).open(dst)?;
+/// Percent-encodes every byte except the unreserved characters of RFC 2396 and `/`.
+fn percent_encode(bytes: &[u8]) -> String {
+    let mut result = String::with_capacity(bytes.len());
+    for &byte in bytes {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                result.push(byte as char)
+            }
+            _ => result.push_str(&format!("%{:02X}", byte)),
+        }
     }
-    Ok(())
+    result
 }
 
-fn parse_uri_path(absolute_file_path: impl AsRef<Path>) -> String {
-    let file_path_chars = absolute_file_path.as_ref().to_str().unwrap().chars();
-    let url: String = "file://".chars().chain(file_path_chars).collect();
-    return url::Url::parse(&url).unwrap().to_file_path().unwrap().to_str().unwrap().into();
+/// Decodes every valid `%XX` sequence and keeps everything else as is.
+fn percent_decode(encoded: &str) -> Vec<u8> {
+    fn hex_value(byte: u8) -> Option<u8> {
+        (byte as char).to_digit(16).map(|digit| digit as u8)
+    }
+    let bytes = encoded.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let (Some(high), Some(low)) = (hex_value(bytes[i + 1]), hex_value(bytes[i + 2])) {
+                result.push(high * 16 + low);
+                i += 3;
+                continue;
+            }
+        }
+        result.push(bytes[i]);
+        i += 1;
+    }
+    result
 }
 
-fn encode_uri_path(absolute_file_path: impl AsRef<Path>) -> String {
-    let url = url::Url::from_file_path(absolute_file_path.as_ref()).unwrap();
-    url.path().to_owned()
+/// Selects the trash folder that the deleted items are moved into.
+///
+/// See [`TrashContextExtFreedesktop::set_target_policy`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TrashTargetPolicy {
+    /// Items are moved into the trash folder of the partition they are on, and into the home
+    /// trash if they are on the root partition. This is what the specification recommends, as it
+    /// avoids copying the items. See [`TrashContextExtFreedesktop::set_cross_device_fallback`]
+    /// for what happens if the trash folder of the partition can't be used.
+    #[default]
+    PreferPerMount,
+
+    /// All items are moved into the home trash, so the items of a removable drive stay in the
+    /// trash when the drive is unplugged. Items on another device than the home trash are copied
+    /// into it and then removed, like with
+    /// [`TrashContextExtFreedesktop::set_cross_device_fallback`].
+    PreferHome,
+
+    /// All items are moved into this folder, which is created if it doesn't exist. The path must
+    /// be absolute: with a relative one trashing fails with an [`Error::FileSystem`] of the kind
+    /// `InvalidInput`, and the folder is not listed. Items on another device than the folder are
+    /// copied into it and then removed.
+    ///
+    /// The folder is listed along with the other trash folders.
+    Explicit(PathBuf),
 }
 
-#[derive(Eq, PartialEq, Debug)]
-enum TrashValidity {
+/// Whether a `$topdir/.Trash` folder may be used according to the specification.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TrashValidity {
     Valid,
+
+    /// The folder is a symbolic link, so it must not be used.
     InvalidSymlink,
+
+    /// The folder doesn't have the sticky bit set, so it must not be used.
     InvalidNotSticky,
 }
 
 fn folder_validity(path: impl AsRef<Path>) -> Result<TrashValidity, Error> {
     /// Mask for the sticky bit
     /// Taken from: http://man7.org/linux/man-pages/man7/inode.7.html
-    const S_ISVTX: u32 = 0x1000;
+    const S_ISVTX: u32 = 0o1000;
 
-    let metadata = path.as_ref().symlink_metadata().map_err(|e| fsys_err_to_unknown(path, e))?;
+    let metadata = path.as_ref().symlink_metadata().map_err(|e| fsys_err(path, e))?;
     if metadata.file_type().is_symlink() {
         return Ok(TrashValidity::InvalidSymlink);
     }
@@ -551,26 +2148,24 @@ fn folder_validity(path: impl AsRef<Path>) -> Result<TrashValidity, Error> {
 /// https://specifications.freedesktop.org/trash-spec/trashspec-1.0.html
 fn home_trash() -> Result<PathBuf, Error> {
     if let Some(data_home) = std::env::var_os("XDG_DATA_HOME") {
-        if data_home.len() > 0 {
+        if !data_home.is_empty() {
             let data_home_path = AsRef::<Path>::as_ref(data_home.as_os_str());
             return Ok(data_home_path.join("Trash"));
         }
     }
     if let Some(home) = std::env::var_os("HOME") {
-        if home.len() > 0 {
+        if !home.is_empty() {
             let home_path = AsRef::<Path>::as_ref(home.as_os_str());
             return Ok(home_path.join(".local/share/Trash"));
         }
     }
-    Err(Error::Unknown {
-        description: "Neither the XDG_DATA_HOME nor the HOME environment variable was found".into(),
-    })
+    Err(Error::NoHomeTrash)
 }
 
 struct MountPoint {
     mnt_dir: PathBuf,
-    _mnt_type: String,
-    _mnt_fsname: String,
+    mnt_type: String,
+    mnt_fsname: String,
 }
 
 fn get_mount_points() -> Result<Vec<MountPoint>, Error> {
@@ -579,63 +2174,151 @@ fn get_mount_points() -> Result<Vec<MountPoint>, Error> {
     let mounts_path = CString::new("/proc/mounts").unwrap();
     let mut file =
         unsafe { libc::fopen(mounts_path.as_c_str().as_ptr(), read_arg.as_c_str().as_ptr()) };
-    if file == std::ptr::null_mut() {
+    let mut opened_path = "/proc/mounts";
+    if file.is_null() {
+        let mounts_error = io::Error::last_os_error();
         let mtab_path = CString::new("/etc/mtab").unwrap();
         file = unsafe { libc::fopen(mtab_path.as_c_str().as_ptr(), read_arg.as_c_str().as_ptr()) };
-    }
-    if file == std::ptr::null_mut() {
-        return Err(Error::Unknown {
-            description: "Neither '/proc/mounts' nor '/etc/mtab' could be opened.".into(),
-        });
+        if file.is_null() {
+            // Neither '/proc/mounts' nor '/etc/mtab' could be opened. The error of the preferred
+            // file is returned, the fallback's is only logged.
+            warn!("Failed to open /etc/mtab as well: {}", io::Error::last_os_error());
+            return Err(fsys_err("/proc/mounts", mounts_error));
+        }
+        opened_path = "/etc/mtab";
     }
     defer! { unsafe { libc::fclose(file); } }
     let mut result = Vec::new();
     loop {
         let mntent = unsafe { libc::getmntent(file) };
-        if mntent == std::ptr::null_mut() {
+        if mntent.is_null() {
             break;
         }
-        let dir = unsafe { CStr::from_ptr((*mntent).mnt_dir).to_str().unwrap() };
-        if dir.bytes().len() == 0 {
+        let dir = unsafe { CStr::from_ptr((*mntent).mnt_dir).to_bytes() };
+        if dir.is_empty() {
             continue;
         }
         let mount_point = unsafe {
             MountPoint {
-                mnt_dir: dir.into(),
-                _mnt_fsname: CStr::from_ptr((*mntent).mnt_fsname).to_str().unwrap().into(),
-                _mnt_type: CStr::from_ptr((*mntent).mnt_type).to_str().unwrap().into(),
+                mnt_dir: OsStr::from_bytes(dir).into(),
+                mnt_fsname: CStr::from_ptr((*mntent).mnt_fsname).to_string_lossy().into_owned(),
+                mnt_type: CStr::from_ptr((*mntent).mnt_type).to_string_lossy().into_owned(),
             }
         };
         result.push(mount_point);
     }
     if result.is_empty() {
-        return Err(Error::Unknown {
-            description:
-                "A mount points file could be opened, but the call to `getmntent` returned NULL."
-                    .into(),
-        });
+        let source = io::Error::new(io::ErrorKind::InvalidData, "no mount point could be read");
+        return Err(fsys_err(opened_path, source));
     }
     Ok(result)
 }
 
+/// Converts a file system error to a crate `Error`
+fn fsys_err<P: AsRef<Path>>(path: P, source: std::io::Error) -> Error {
+    Error::FileSystem { path: path.as_ref().to_owned(), source }
+}
+
 #[cfg(test)]
 mod tests {
     use std::{
         collections::{hash_map::Entry, HashMap},
         env,
-        ffi::OsString,
+        ffi::{CString, OsStr, OsString},
         fs::File,
+        os::unix::{
+            ffi::OsStrExt,
+            fs::{MetadataExt, PermissionsExt},
+            io::AsRawFd,
+        },
         path::{Path, PathBuf},
         process::Command,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc, Mutex,
+        },
+        time::Duration,
     };
 
+    use super::{
+        copy_out_of_trash, copy_to_trash_and_remove, item_sizes, percent_decode, percent_encode,
+        read_directory_sizes, update_directory_sizes, DirectorySize, ProgressTracker,
+        TrashFolderKind, TrashInfo, TrashTargetPolicy, TrashValidity,
+    };
     use crate::{
         canonicalize_paths,
-        os_limited::{list, purge_all},
-        tests::get_unique_name,
-        Error,
+        freedesktop::{fsck::QUARANTINE_FOLDER, TrashContextExtFreedesktop},
+        os_limited::{
+            list, purge_all, RestoreCollisionPolicy, RestoreOptions, RestoreOutcome,
+            TrashContextExtOsLimited,
+        },
+        tests::{get_unique_name, init_logging},
+        BatchMode, CancellationToken, Error, Progress, TrashContext, TrashItemKind, TrashOperation,
     };
 
+    /// Creates a new folder in the temporary directory of the system and returns a context that
+    /// uses `<folder>/home/Trash` as the home trash and `<folder>/mnt` as the only topdir.
+    pub(crate) fn isolated_context() -> (TrashContext, PathBuf) {
+        let root = env::temp_dir().join(get_unique_name());
+        std::fs::create_dir_all(root.join("mnt")).unwrap();
+        let root = root.canonicalize().unwrap();
+        let mut ctx = TrashContext::default();
+        ctx.set_home_trash(Some(root.join("home").join("Trash")));
+        ctx.set_topdirs(Some(vec![root.join("mnt")]));
+        (ctx, root)
+    }
+
+    #[test]
+    fn test_isolated_context() {
+        init_logging();
+        let (ctx, root) = isolated_context();
+        let uid = unsafe { libc::getuid() };
+        let in_home = root.join("in-home");
+        let in_mnt = root.join("mnt").join("in-mnt");
+        File::create(&in_home).unwrap();
+        File::create(&in_mnt).unwrap();
+        ctx.delete_all([&in_home, &in_mnt]).unwrap();
+        assert!(!in_home.exists());
+        assert!(!in_mnt.exists());
+        assert!(root.join("home/Trash/files/in-home").exists());
+        assert!(root.join(format!("mnt/.Trash-{}/files/in-mnt", uid)).exists());
+
+        let mut items = ctx.list().unwrap();
+        items.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].original_path(), in_home);
+        assert_eq!(items[1].original_path(), in_mnt);
+
+        ctx.purge_all(items).unwrap();
+        assert!(ctx.list().unwrap().is_empty());
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_delete_all_returning_items() {
+        init_logging();
+        let (ctx, root) = isolated_context();
+        let paths = [root.join("returned"), root.join("mnt").join("returned")];
+        for path in paths.iter() {
+            File::create(path).unwrap();
+        }
+        let returned = ctx.delete_all_returning_items(&paths).unwrap();
+        assert_eq!(returned.len(), paths.len());
+        let listed = ctx.list().unwrap();
+        for (item, path) in returned.iter().zip(paths.iter()) {
+            assert_eq!(&item.original_path(), path);
+            let listed_item = listed.iter().find(|x| x.id == item.id).unwrap();
+            assert_eq!(listed_item.name, item.name);
+            assert_eq!(listed_item.original_parent, item.original_parent);
+            assert_eq!(listed_item.time_deleted, item.time_deleted);
+        }
+        ctx.restore_all(returned).unwrap();
+        for path in paths.iter() {
+            assert!(path.exists());
+        }
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
     #[test]
     fn test_list() {
         let file_name_prefix = get_unique_name();
@@ -652,9 +2335,9 @@ mod tests {
         let items = list().unwrap();
         let items: HashMap<_, Vec<_>> = items
             .into_iter()
-            .filter(|x| x.name.starts_with(&file_name_prefix))
+            .filter(|x| x.name_lossy().starts_with(&file_name_prefix))
             .fold(HashMap::new(), |mut map, x| {
-                match map.entry(x.name.clone()) {
+                match map.entry(x.name_lossy().into_owned()) {
                     Entry::Occupied(mut entry) => {
                         entry.get_mut().push(x);
                     }
@@ -673,7 +2356,924 @@ mod tests {
 
         // Let's try to purge all the items we just created but ignore any errors
         // as this test should succeed as long as `list` works properly.
-        let _ = purge_all(items.into_iter().map(|(_name, item)| item).flatten());
+        let _ = purge_all(items.into_values().flatten());
+    }
+
+    #[test]
+    fn test_fallback_to_home_trash() {
+        init_logging();
+        let (mut ctx, root) = isolated_context();
+        let uid = unsafe { libc::getuid() };
+        // Make it impossible to create the trash folder of the topdir
+        File::create(root.join(format!("mnt/.Trash-{}", uid))).unwrap();
+        let path = root.join("mnt").join("falling-back");
+        File::create(&path).unwrap();
+
+        assert!(ctx.delete(&path).is_err());
+        assert!(path.exists());
+
+        ctx.set_cross_device_fallback(true);
+        let item = ctx.delete_returning_item(&path).unwrap();
+        assert!(!path.exists());
+        assert!(Path::new(&item.id).starts_with(root.join("home/Trash/info")));
+        let listed = ctx.list().unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].original_path(), path);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_target_policy() {
+        init_logging();
+        let (mut ctx, root) = isolated_context();
+        let uid = unsafe { libc::getuid() };
+        let path = root.join("mnt").join("targeted");
+
+        File::create(&path).unwrap();
+        let item = ctx.delete_returning_item(&path).unwrap();
+        assert!(Path::new(&item.id).starts_with(root.join(format!("mnt/.Trash-{}/info", uid))));
+        ctx.restore_all([item]).unwrap();
+
+        ctx.set_target_policy(TrashTargetPolicy::PreferHome);
+        let item = ctx.delete_returning_item(&path).unwrap();
+        assert!(Path::new(&item.id).starts_with(root.join("home/Trash/info")));
+        assert_eq!(TrashInfo::read(Path::new(&item.id)).unwrap().path, path);
+        ctx.restore_all([item]).unwrap();
+
+        let custom = root.join("custom").join("Trash");
+        ctx.set_target_policy(TrashTargetPolicy::Explicit(custom.clone()));
+        let item = ctx.delete_returning_item(&path).unwrap();
+        assert!(Path::new(&item.id).starts_with(custom.join("info")));
+        assert!(custom.join("files/targeted").exists());
+        assert_eq!(ctx.list().unwrap(), std::slice::from_ref(&item));
+        let folders = ctx.trash_folders().unwrap();
+        let explicit = folders.iter().find(|folder| folder.path == custom).unwrap();
+        assert_eq!(explicit.kind, TrashFolderKind::Explicit);
+        ctx.restore_all([item]).unwrap();
+        assert!(path.exists());
+
+        ctx.set_target_policy(TrashTargetPolicy::Explicit("relative/Trash".into()));
+        match ctx.delete(&path) {
+            Err(Error::FileSystem { path: folder, source }) => {
+                assert_eq!(folder, Path::new("relative/Trash"));
+                assert_eq!(source.kind(), std::io::ErrorKind::InvalidInput);
+            }
+            other => panic!("Expected an InvalidInput error, got {:?}", other),
+        }
+        assert!(path.exists());
+        assert!(ctx.trash_folders().unwrap().iter().all(|folder| folder.path.is_absolute()));
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    #[ignore = "needs /dev/shm to be on another device than the temporary directory"]
+    fn test_cross_device_copy() {
+        init_logging();
+        let (mut ctx, root) = isolated_context();
+        let other_device = Path::new("/dev/shm");
+        assert_ne!(
+            other_device.metadata().unwrap().dev(),
+            root.metadata().unwrap().dev(),
+            "{:?} must be on another device for the fallback to run",
+            other_device
+        );
+        let src = other_device.join(get_unique_name());
+        std::fs::create_dir_all(src.join("nested")).unwrap();
+        std::fs::write(src.join("nested").join("file"), b"0123456789").unwrap();
+        std::os::unix::fs::symlink("nested/file", src.join("link")).unwrap();
+
+        let bytes_reported = Arc::new(AtomicU64::new(0));
+        let bytes_reported_clone = bytes_reported.clone();
+        ctx.set_progress_observer(Some(Arc::new(move |progress: &Progress| {
+            bytes_reported_clone.store(progress.bytes_processed, Ordering::SeqCst);
+        })));
+        ctx.set_topdirs(Some(vec![]));
+
+        assert!(ctx.delete(&src).is_err());
+        assert!(src.exists());
+
+        ctx.set_cross_device_fallback(true);
+        let item = ctx.delete_returning_item(&src).unwrap();
+        assert!(!src.exists());
+        assert_eq!(bytes_reported.load(Ordering::SeqCst), 10);
+        let in_trash = root.join("home/Trash/files").join(&item.name);
+        assert_eq!(std::fs::read(in_trash.join("nested/file")).unwrap(), b"0123456789");
+        assert_eq!(std::fs::read_link(in_trash.join("link")).unwrap(), Path::new("nested/file"));
+
+        bytes_reported.store(0, Ordering::SeqCst);
+        let restored = ctx.restore_to(item, &src, Some(OsStr::new("restored"))).unwrap();
+        assert_eq!(restored, src.join("restored"));
+        assert!(!in_trash.exists());
+        assert_eq!(bytes_reported.load(Ordering::SeqCst), 10);
+        assert_eq!(std::fs::read(restored.join("nested/file")).unwrap(), b"0123456789");
+        assert!(ctx.list().unwrap().is_empty());
+        std::fs::remove_dir_all(src).unwrap();
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    /// Creates a directory at `path` with a nested directory, two files of 10 bytes each, and a
+    /// symbolic link.
+    #[cfg(target_os = "linux")]
+    fn create_tree(path: &Path) {
+        std::fs::create_dir_all(path.join("a/nested")).unwrap();
+        std::fs::write(path.join("a/nested/file"), b"0123456789").unwrap();
+        std::fs::write(path.join("b"), b"0123456789").unwrap();
+        std::os::unix::fs::symlink("a/nested/file", path.join("link")).unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    fn assert_tree(path: &Path) {
+        assert_eq!(std::fs::read(path.join("a/nested/file")).unwrap(), b"0123456789");
+        assert_eq!(std::fs::read(path.join("b")).unwrap(), b"0123456789");
+        assert_eq!(std::fs::read_link(path.join("link")).unwrap(), Path::new("a/nested/file"));
+    }
+
+    #[cfg(target_os = "linux")]
+    fn create_fifo(path: &Path) {
+        let path = CString::new(path.as_os_str().as_bytes()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(path.as_ptr(), 0o644) }, 0);
+    }
+
+    /// Makes it impossible to add or remove entries of `dir`, also for root which ignores
+    /// permissions.
+    #[cfg(target_os = "linux")]
+    fn set_locked(dir: &Path, locked: bool) {
+        if unsafe { libc::geteuid() } == 0 {
+            // `FS_IMMUTABLE_FL` from `linux/fs.h`
+            const IMMUTABLE: libc::c_int = 0x10;
+            let dir = File::open(dir).unwrap();
+            let mut flags: libc::c_int = 0;
+            assert_eq!(
+                unsafe { libc::ioctl(dir.as_raw_fd(), libc::FS_IOC_GETFLAGS, &mut flags) },
+                0
+            );
+            flags = if locked { flags | IMMUTABLE } else { flags & !IMMUTABLE };
+            assert_eq!(unsafe { libc::ioctl(dir.as_raw_fd(), libc::FS_IOC_SETFLAGS, &flags) }, 0);
+        } else {
+            let mode = if locked { 0o555 } else { 0o755 };
+            std::fs::set_permissions(dir, std::fs::Permissions::from_mode(mode)).unwrap();
+        }
+    }
+
+    /// Sets a progress observer that locks `src/a` with `set_locked` once `src` was copied, so
+    /// that removing `src` fails after `src/a/nested/file` was removed.
+    #[cfg(target_os = "linux")]
+    fn lock_after_copy(ctx: &mut TrashContext, src: &Path) {
+        let src = src.to_owned();
+        ctx.set_progress_observer(Some(Arc::new(move |progress: &Progress| {
+            if progress.current_path == src {
+                set_locked(&src.join("a"), true);
+            }
+        })));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_copy_to_trash_and_remove() {
+        init_logging();
+        let (mut ctx, root) = isolated_context();
+        let bytes_reported = Arc::new(AtomicU64::new(0));
+        let bytes_reported_clone = bytes_reported.clone();
+        ctx.set_progress_observer(Some(Arc::new(move |progress: &Progress| {
+            bytes_reported_clone.store(progress.bytes_processed, Ordering::SeqCst);
+        })));
+        let src = root.join("src");
+        let dst = root.join("files").join("src");
+        let info_file = root.join("src.trashinfo");
+        std::fs::create_dir(root.join("files")).unwrap();
+        create_tree(&src);
+        File::create(&info_file).unwrap();
+
+        let mut tracker = ProgressTracker::new(&ctx, TrashOperation::Delete);
+        copy_to_trash_and_remove(&src, &dst, &info_file, &mut tracker).unwrap();
+        assert!(!src.exists());
+        assert!(info_file.exists());
+        assert_tree(&dst);
+        assert_eq!(bytes_reported.load(Ordering::SeqCst), 20);
+        std::fs::remove_dir_all(&dst).unwrap();
+
+        // Copying fails, the partial copy and the info file are removed.
+        create_tree(&src);
+        create_fifo(&src.join("fifo"));
+        let mut tracker = ProgressTracker::new(&ctx, TrashOperation::Delete);
+        match copy_to_trash_and_remove(&src, &dst, &info_file, &mut tracker) {
+            Err(Error::FileSystem { path, .. }) => assert_eq!(path, src),
+            other => panic!("Expected a FileSystem error, got {:?}", other),
+        }
+        assert!(dst.symlink_metadata().is_err());
+        assert!(!info_file.exists());
+        assert_tree(&src);
+        std::fs::remove_file(src.join("fifo")).unwrap();
+
+        // Removing `src` fails partway, the removed entries are copied back.
+        File::create(&info_file).unwrap();
+        lock_after_copy(&mut ctx, &src);
+        let mut tracker = ProgressTracker::new(&ctx, TrashOperation::Delete);
+        let result = copy_to_trash_and_remove(&src, &dst, &info_file, &mut tracker);
+        set_locked(&src.join("a"), false);
+        match result {
+            Err(Error::FileSystem { path, .. }) => assert_eq!(path, src),
+            other => panic!("Expected a FileSystem error, got {:?}", other),
+        }
+        assert!(dst.symlink_metadata().is_err());
+        assert!(!info_file.exists());
+        assert_tree(&src);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_copy_out_of_trash() {
+        init_logging();
+        let (mut ctx, root) = isolated_context();
+        let bytes_reported = Arc::new(AtomicU64::new(0));
+        let bytes_reported_clone = bytes_reported.clone();
+        ctx.set_progress_observer(Some(Arc::new(move |progress: &Progress| {
+            bytes_reported_clone.store(progress.bytes_processed, Ordering::SeqCst);
+        })));
+        let file = root.join("files").join("item");
+        let target = root.join("target");
+        create_tree(&file);
+
+        let mut tracker = ProgressTracker::new(&ctx, TrashOperation::Restore);
+        copy_out_of_trash(&file, &target, &mut tracker).unwrap();
+        assert!(!file.exists());
+        assert_tree(&target);
+        assert_eq!(bytes_reported.load(Ordering::SeqCst), 20);
+        std::fs::remove_dir_all(&target).unwrap();
+
+        // Copying fails, the partial copy is removed and the item stays in the trash.
+        create_tree(&file);
+        create_fifo(&file.join("fifo"));
+        let mut tracker = ProgressTracker::new(&ctx, TrashOperation::Restore);
+        match copy_out_of_trash(&file, &target, &mut tracker) {
+            Err(Error::FileSystem { path, .. }) => assert_eq!(path, file),
+            other => panic!("Expected a FileSystem error, got {:?}", other),
+        }
+        assert!(target.symlink_metadata().is_err());
+        assert_tree(&file);
+        std::fs::remove_file(file.join("fifo")).unwrap();
+
+        // Removing the item from the trash fails partway, the removed entries are copied back.
+        lock_after_copy(&mut ctx, &file);
+        let mut tracker = ProgressTracker::new(&ctx, TrashOperation::Restore);
+        let result = copy_out_of_trash(&file, &target, &mut tracker);
+        set_locked(&file.join("a"), false);
+        match result {
+            Err(Error::FileSystem { path, .. }) => assert_eq!(path, file),
+            other => panic!("Expected a FileSystem error, got {:?}", other),
+        }
+        assert!(target.symlink_metadata().is_err());
+        assert_tree(&file);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_delete_all_atomic() {
+        init_logging();
+        let (ctx, root) = isolated_context();
+        let file = root.join("file");
+        let dir = root.join("dir");
+        let child = dir.join("child");
+        File::create(&file).unwrap();
+        std::fs::create_dir(&dir).unwrap();
+        File::create(&child).unwrap();
+
+        // Fails during validation, nothing is moved and no trash folder is created.
+        let missing = root.join("missing");
+        let in_mnt = root.join("mnt").join("file");
+        File::create(&in_mnt).unwrap();
+        let report = ctx.delete_all_atomic([&in_mnt, &missing, &file, &missing]).unwrap_err();
+        assert!(report.succeeded.is_empty());
+        let failed = report.failed.iter().map(|(path, _)| path).collect::<Vec<_>>();
+        assert_eq!(failed, [&missing, &missing]);
+        assert_eq!(report.not_attempted, [in_mnt.clone(), file.clone()]);
+        assert!(file.exists() && in_mnt.exists());
+        assert!(!root.join("home").exists());
+        assert_eq!(std::fs::read_dir(root.join("mnt")).unwrap().count(), 1);
+
+        // The child vanishes once its parent is trashed, so the parent has to be rolled back.
+        let report = ctx.delete_all_atomic([&file, &dir, &child]).unwrap_err();
+        assert!(report.succeeded.is_empty());
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].0, child);
+        assert_eq!(report.not_attempted, [file.clone(), dir.clone()]);
+        assert!(file.exists() && child.exists());
+        assert!(ctx.list().unwrap().is_empty());
+
+        let items = ctx.delete_all_atomic([&file, &dir]).unwrap();
+        assert_eq!(items.len(), 2);
+        assert!(!file.exists() && !dir.exists());
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_progress() {
+        init_logging();
+        let (mut ctx, root) = isolated_context();
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let reports_clone = reports.clone();
+        ctx.set_progress_observer(Some(Arc::new(move |progress: &Progress| {
+            reports_clone.lock().unwrap().push((
+                progress.operation,
+                progress.items_processed,
+                progress.bytes_processed,
+            ));
+        })));
+        let dir = root.join("dir");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("file"), b"0123456789").unwrap();
+        let file = root.join("file");
+        File::create(&file).unwrap();
+
+        ctx.delete_all([&dir, &file]).unwrap();
+        let delete_reports = std::mem::take(&mut *reports.lock().unwrap());
+        assert_eq!(
+            delete_reports,
+            [(TrashOperation::Delete, 1, 0), (TrashOperation::Delete, 2, 0)]
+        );
+
+        let mut items = ctx.list().unwrap();
+        items.sort_by(|a, b| a.name.cmp(&b.name));
+        let file_item = items.pop().unwrap();
+        ctx.restore_all([file_item]).unwrap();
+        let restore_reports = std::mem::take(&mut *reports.lock().unwrap());
+        assert_eq!(restore_reports, [(TrashOperation::Restore, 1, 0)]);
+
+        ctx.purge_all(items).unwrap();
+        let purge_reports = std::mem::take(&mut *reports.lock().unwrap());
+        assert!(purge_reports.iter().all(|(operation, ..)| *operation == TrashOperation::Purge));
+        assert_eq!(purge_reports.last(), Some(&(TrashOperation::Purge, 1, 10)));
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_cancellation() {
+        init_logging();
+        let (mut ctx, root) = isolated_context();
+        let token = CancellationToken::new();
+        let file = root.join("file");
+        File::create(&file).unwrap();
+        let item = ctx.delete_returning_item(&file).unwrap();
+
+        ctx.set_cancellation_token(Some(token.clone()));
+        token.cancel();
+        let other = root.join("other");
+        File::create(&other).unwrap();
+        assert!(matches!(ctx.delete(&other), Err(Error::Cancelled)));
+        assert!(other.exists());
+        assert!(matches!(ctx.purge_all([item.clone()]), Err(Error::Cancelled)));
+        assert!(matches!(ctx.restore_all([item.clone()]), Err(Error::Cancelled)));
+        assert!(!file.exists());
+        assert_eq!(ctx.list().unwrap(), [item]);
+
+        ctx.set_cancellation_token(None);
+        ctx.purge_all(ctx.list().unwrap()).unwrap();
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_io_error_kinds() {
+        init_logging();
+        let (ctx, root) = isolated_context();
+        let missing = root.join("missing");
+        match ctx.delete(&missing) {
+            Err(Error::FileSystem { path, source }) => {
+                assert_eq!(path, missing);
+                assert_eq!(source.kind(), std::io::ErrorKind::NotFound);
+            }
+            other => panic!("Expected a FileSystem error, got {:?}", other),
+        }
+        match ctx.delete(root.join("missing-parent").join("file")) {
+            Err(error @ Error::CanonicalizePath { .. }) => {
+                let source = std::error::Error::source(&error).unwrap();
+                let source = source.downcast_ref::<std::io::Error>().unwrap();
+                assert_eq!(source.kind(), std::io::ErrorKind::NotFound);
+            }
+            other => panic!("Expected a CanonicalizePath error, got {:?}", other),
+        }
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_trash_info() {
+        let parse = |contents: &str| TrashInfo::parse(contents);
+        let info = parse("# comment\n\n[Trash Info]\nPath = /a%20b/c=d\nX-Unknown=1\nDeletionDate=2004-08-31T22:32:08\n[Other]\nPath=/ignored\n").unwrap();
+        assert_eq!(info.path, Path::new("/a b/c=d"));
+        assert_eq!(info.deletion_date.to_string(), "2004-08-31 22:32:08");
+        assert_eq!(
+            info.to_string(),
+            "[Trash Info]\nPath=/a%20b/c%3Dd\nDeletionDate=2004-08-31T22:32:08\n"
+        );
+        assert_eq!(parse(&info.to_string()).unwrap(), info);
+
+        let relative =
+            parse("[Trash Info]\nPath=dir/%C3%A9\nDeletionDate=2004-08-31T22:32:08").unwrap();
+        assert_eq!(relative.path, Path::new("dir/é"));
+
+        let invalid = [
+            "",
+            "Path=/a\nDeletionDate=2004-08-31T22:32:08",
+            "[Trash Info]\nDeletionDate=2004-08-31T22:32:08",
+            "[Trash Info]\nPath=/a",
+            "[Trash Info]\nPath=\nDeletionDate=2004-08-31T22:32:08",
+            "[Trash Info]\nPath=/a\nDeletionDate=yesterday",
+            "[Trash Info]\nPath=/a\nnot a key value pair\nDeletionDate=2004-08-31T22:32:08",
+            "[Other]\nPath=/a\nDeletionDate=2004-08-31T22:32:08",
+        ];
+        for contents in invalid {
+            match parse(contents) {
+                Err(Error::InvalidTrashInfo { path, .. }) => assert_eq!(path, PathBuf::new()),
+                other => panic!("{:?} should be invalid, got {:?}", contents, other),
+            }
+        }
+
+        let root = env::temp_dir().join(get_unique_name());
+        std::fs::create_dir_all(&root).unwrap();
+        let info_file = root.join("invalid.trashinfo");
+        std::fs::write(&info_file, "[Trash Info]\nPath=/a").unwrap();
+        match TrashInfo::read(&info_file) {
+            Err(Error::InvalidTrashInfo { path, reason }) => {
+                assert_eq!(path, info_file);
+                assert_eq!(reason, "missing DeletionDate");
+            }
+            other => panic!("Expected an InvalidTrashInfo, got {:?}", other),
+        }
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_list_iter_yields_invalid_info_files() {
+        init_logging();
+        let (ctx, root) = isolated_context();
+        let file = root.join("file");
+        File::create(&file).unwrap();
+        ctx.delete(&file).unwrap();
+        std::fs::write(root.join("home/Trash/info/broken.trashinfo"), "broken").unwrap();
+
+        let results: Vec<_> = ctx.list_iter().unwrap().collect();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results.iter().filter(|x| x.is_err()).count(), 1);
+        let item = results.into_iter().find_map(Result::ok).unwrap();
+        assert_eq!(item.original_path(), file);
+
+        std::fs::remove_dir_all(root.join("home/Trash/info")).unwrap();
+        assert!(ctx.list().is_err());
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_list_skips_invalid_info_files() {
+        init_logging();
+        let (ctx, root) = isolated_context();
+        let file = root.join("a=b");
+        File::create(&file).unwrap();
+        ctx.delete(&file).unwrap();
+        let info_folder = root.join("home/Trash/info");
+        std::fs::write(info_folder.join("broken.trashinfo"), "[Trash Info]\n=\n\nPath").unwrap();
+        std::fs::write(info_folder.join("empty.trashinfo"), "").unwrap();
+
+        let items = ctx.list().unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].name, "a=b");
+        assert_eq!(items[0].original_path(), file);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_admin_trash_relative_path() {
+        init_logging();
+        let (ctx, root) = isolated_context();
+        let admin_trash = root.join("mnt/.Trash");
+        std::fs::create_dir_all(&admin_trash).unwrap();
+        std::fs::set_permissions(&admin_trash, std::fs::Permissions::from_mode(0o1777)).unwrap();
+        let uid = unsafe { libc::getuid() };
+        std::fs::create_dir(admin_trash.join(uid.to_string())).unwrap();
+        let file = root.join("mnt/dir/file");
+        std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+        File::create(&file).unwrap();
+
+        let item = ctx.delete_returning_item(&file).unwrap();
+        let info_path = admin_trash.join(uid.to_string()).join("info/file.trashinfo");
+        assert_eq!(Path::new(&item.id), info_path);
+        assert_eq!(TrashInfo::read(&info_path).unwrap().path, Path::new("dir/file"));
+        let items = ctx.list().unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].original_path(), file);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_non_utf8_names() {
+        init_logging();
+        let (ctx, root) = isolated_context();
+        let name = OsStr::from_bytes(b"caf\xe9 \xff");
+        let file = root.join(name);
+        std::fs::write(&file, b"latin-1").unwrap();
+
+        let item = ctx.delete_returning_item(&file).unwrap();
+        assert_eq!(item.name, name);
+        assert_eq!(item.name_lossy(), "caf\u{FFFD} \u{FFFD}");
+        let info = std::fs::read_to_string(&item.id).unwrap();
+        assert!(info.contains("/caf%E9%20%FF\n"), "{}", info);
+
+        let items = ctx.list().unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].name, name);
+        assert_eq!(items[0].original_path(), file);
+        ctx.restore_all(items).unwrap();
+        assert_eq!(std::fs::read(&file).unwrap(), b"latin-1");
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_expire() {
+        init_logging();
+        let (ctx, root) = isolated_context();
+        let old = root.join("old");
+        let new = root.join("new");
+        std::fs::write(&old, b"old").unwrap();
+        std::fs::write(&new, b"new").unwrap();
+        let dangling = root.join("dangling");
+        std::fs::write(&dangling, b"dangling").unwrap();
+        let items = ctx.delete_all_returning_items([&old, &new, &dangling]).unwrap();
+        for item in [&items[0], &items[2]] {
+            let mut info = TrashInfo::read(&item.id).unwrap();
+            info.deletion_date =
+                chrono::NaiveDate::from_ymd_opt(2000, 1, 1).unwrap().and_hms_opt(0, 0, 0).unwrap();
+            std::fs::write(&item.id, info.to_string()).unwrap();
+        }
+        std::fs::remove_file(root.join("home/Trash/files/dangling")).unwrap();
+        let max_age = Duration::from_secs(30 * 24 * 60 * 60);
+
+        let report = ctx.expire(max_age, true).unwrap();
+        assert_eq!(report.expired, [items[0].clone()]);
+        assert_eq!(report.bytes, items[0].metadata().unwrap().size);
+        assert!(report.bytes > 0);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].0, items[2]);
+        assert_eq!(ctx.list().unwrap().len(), 3);
+
+        let report = ctx.expire(max_age, false).unwrap();
+        assert_eq!(report.expired, [items[0].clone()]);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].0, items[2]);
+        let mut remaining = ctx.list().unwrap();
+        remaining.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(remaining, [items[2].clone(), items[1].clone()]);
+        assert!(ctx.expire(max_age, false).unwrap().expired.is_empty());
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_enforce_quota() {
+        init_logging();
+        let (ctx, root) = isolated_context();
+        let mut items = Vec::new();
+        for (i, name) in ["first", "second", "third"].iter().enumerate() {
+            let file = root.join("mnt").join(name);
+            std::fs::write(&file, vec![0; 8192]).unwrap();
+            let mut item = ctx.delete_returning_item(&file).unwrap();
+            let mut info = TrashInfo::read(&item.id).unwrap();
+            info.deletion_date = chrono::NaiveDate::from_ymd_opt(2020, 1, 1 + i as u32)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap();
+            std::fs::write(&item.id, info.to_string()).unwrap();
+            item.time_deleted = info.time_deleted().unwrap();
+            items.push(item);
+        }
+        let in_home = root.join("in-home");
+        std::fs::write(&in_home, vec![0; 8192]).unwrap();
+        let home_item = ctx.delete_returning_item(&in_home).unwrap();
+        let sizes: Vec<u64> = items.iter().map(|item| item.metadata().unwrap().size).collect();
+        let home_size = home_item.metadata().unwrap().size;
+        let mnt_size: u64 = sizes.iter().sum();
+
+        let folder = root.join("mnt").join(format!(".Trash-{}", unsafe { libc::getuid() }));
+        match ctx.enforce_folder_quota(&root.join("mnt"), 0) {
+            Err(Error::NotATrashFolder { path }) => assert_eq!(path, root.join("mnt")),
+            other => panic!("Expected a NotATrashFolder, got {:?}", other),
+        }
+        let dangling = root.join("mnt").join("dangling");
+        File::create(&dangling).unwrap();
+        let dangling_item = ctx.delete_returning_item(&dangling).unwrap();
+        std::fs::remove_file(folder.join("files").join("dangling")).unwrap();
+
+        let indirect = folder.join("..").join(folder.file_name().unwrap());
+        let mut report = ctx.enforce_folder_quota(&indirect, mnt_size - 1).unwrap();
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed.pop().unwrap().0, dangling_item);
+        ctx.purge_all([dangling_item]).unwrap();
+        assert_eq!(report.evicted, [items[0].clone()]);
+        assert_eq!(report.bytes_freed, sizes[0]);
+        assert_eq!(report.bytes_remaining, mnt_size - sizes[0]);
+        assert!(report.failed.is_empty());
+
+        let report = ctx.enforce_quota(home_size).unwrap();
+        assert_eq!(report.evicted, [items[1].clone(), items[2].clone()]);
+        assert_eq!(report.bytes_remaining, home_size);
+        assert_eq!(ctx.list().unwrap(), [home_item]);
+        assert_eq!(ctx.enforce_quota(0).unwrap().bytes_remaining, 0);
+        assert!(ctx.list().unwrap().is_empty());
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_purge_with_report_dangling_info() {
+        init_logging();
+        let (ctx, root) = isolated_context();
+        let files = ["dangling", "intact"].map(|name| root.join(name));
+        for file in &files {
+            File::create(file).unwrap();
+        }
+        let items = ctx.delete_all_returning_items(&files).unwrap();
+        std::fs::remove_file(root.join("home/Trash/files/dangling")).unwrap();
+
+        let report = ctx.purge_all_with_report(items, BatchMode::StopOnError);
+        assert!(report.is_success());
+        assert_eq!(report.succeeded.len(), 2);
+        assert!(ctx.list().unwrap().is_empty());
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_empty() {
+        init_logging();
+        let (ctx, root) = isolated_context();
+        let home_trash = root.join("home/Trash");
+        let mnt_trash = root.join("mnt").join(format!(".Trash-{}", unsafe { libc::getuid() }));
+        let dir = root.join("dir");
+        std::fs::create_dir_all(dir.join("nested")).unwrap();
+        File::create(dir.join("nested/file")).unwrap();
+        File::create(root.join("mnt/file")).unwrap();
+        ctx.delete_all([&dir, &root.join("mnt/file")]).unwrap();
+        assert!(home_trash.join("directorysizes").exists());
+        let orphan_info = |trash: &Path| {
+            let info = TrashInfo {
+                path: "/gone".into(),
+                deletion_date: chrono::Local::now().naive_local(),
+            };
+            std::fs::write(trash.join("info/gone.trashinfo"), info.to_string()).unwrap();
+        };
+        orphan_info(&home_trash);
+        orphan_info(&mnt_trash);
+        std::fs::write(home_trash.join("files/orphan"), b"crashed").unwrap();
+        std::fs::create_dir(home_trash.join(QUARANTINE_FOLDER)).unwrap();
+        std::fs::write(home_trash.join(QUARANTINE_FOLDER).join("bad"), b"bad").unwrap();
+
+        // Purging an item whose file is gone only removes the info file.
+        let gone = ctx
+            .list()
+            .unwrap()
+            .into_iter()
+            .find(|item| Path::new(&item.id).starts_with(&home_trash) && item.name == "gone")
+            .unwrap();
+        ctx.purge_all([gone]).unwrap();
+        assert!(!home_trash.join("info/gone.trashinfo").exists());
+        orphan_info(&home_trash);
+
+        let elsewhere = root.join("elsewhere");
+        std::fs::create_dir_all(elsewhere.join("files")).unwrap();
+        File::create(elsewhere.join("files/precious")).unwrap();
+        match ctx.empty_folder(&elsewhere) {
+            Err(Error::NotATrashFolder { path }) => assert_eq!(path, elsewhere),
+            other => panic!("Expected a NotATrashFolder, got {:?}", other),
+        }
+        assert!(elsewhere.join("files/precious").exists());
+
+        ctx.empty_folder(&home_trash).unwrap();
+        for name in ["files", "info", QUARANTINE_FOLDER] {
+            assert_eq!(std::fs::read_dir(home_trash.join(name)).unwrap().count(), 0);
+        }
+        assert!(!home_trash.join("directorysizes").exists());
+        assert_eq!(ctx.list().unwrap().len(), 2);
+
+        ctx.empty().unwrap();
+        assert!(ctx.list().unwrap().is_empty());
+        assert_eq!(std::fs::read_dir(mnt_trash.join("files")).unwrap().count(), 0);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_trash_folders() {
+        init_logging();
+        let (ctx, root) = isolated_context();
+        let uid = unsafe { libc::getuid() };
+        assert!(ctx.trash_folders().unwrap().is_empty());
+
+        let in_home = root.join("in-home");
+        let in_mnt = root.join("mnt").join("in-mnt");
+        std::fs::write(&in_home, b"home").unwrap();
+        std::fs::write(&in_mnt, b"mnt").unwrap();
+        ctx.delete_all([&in_home, &in_mnt]).unwrap();
+        // Not sticky, so nothing is trashed into it but it's still reported.
+        let admin_trash = root.join("mnt/.Trash");
+        std::fs::create_dir_all(admin_trash.join(uid.to_string())).unwrap();
+        std::fs::set_permissions(&admin_trash, std::fs::Permissions::from_mode(0o777)).unwrap();
+
+        let folders = ctx.trash_folders().unwrap();
+        assert_eq!(folders.len(), 3, "{:?}", folders);
+        assert_eq!(folders[0].kind, TrashFolderKind::Home);
+        assert_eq!(folders[0].path, root.join("home/Trash"));
+        assert_eq!(folders[0].item_count, 1);
+        assert_eq!(folders[1].kind, TrashFolderKind::Admin);
+        assert_eq!(folders[1].path, admin_trash.join(uid.to_string()));
+        assert_eq!(folders[1].topdir, root.join("mnt"));
+        assert_eq!(folders[1].validity, TrashValidity::InvalidNotSticky);
+        assert_eq!(folders[1].item_count, 0);
+        assert_eq!(folders[1].disk_usage().unwrap(), 0);
+        assert_eq!(folders[2].kind, TrashFolderKind::User);
+        assert_eq!(folders[2].path, root.join(format!("mnt/.Trash-{}", uid)));
+        assert_eq!(folders[2].validity, TrashValidity::Valid);
+        assert_eq!(folders[2].item_count, 1);
+        assert!(folders[2].disk_usage().unwrap() > 0);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_restore_to() {
+        init_logging();
+        let (ctx, root) = isolated_context();
+        let dir = root.join("dir");
+        std::fs::create_dir_all(dir.join("nested")).unwrap();
+        std::fs::write(dir.join("nested").join("file"), b"content").unwrap();
+        let item = ctx.delete_returning_item(&dir).unwrap();
+        assert_eq!(read_directory_sizes(root.join("home/Trash")).unwrap().len(), 1);
+
+        let destination = root.join("elsewhere").join("deeper");
+        let restored = ctx.restore_to(item, &destination, None).unwrap();
+        assert_eq!(restored, destination.join("dir"));
+        assert_eq!(std::fs::read(restored.join("nested/file")).unwrap(), b"content");
+        assert!(!dir.exists());
+        assert!(ctx.list().unwrap().is_empty());
+        assert!(read_directory_sizes(root.join("home/Trash")).unwrap().is_empty());
+
+        let file = root.join("file");
+        File::create(&file).unwrap();
+        let item = ctx.delete_returning_item(&file).unwrap();
+        match ctx.restore_to(item, &destination, Some(OsStr::new("dir"))) {
+            Err(Error::RestoreCollision { path, remaining_items }) => {
+                assert_eq!(path, destination.join("dir"));
+                assert_eq!(remaining_items.len(), 1);
+            }
+            other => panic!("Expected a RestoreCollision, got {:?}", other),
+        }
+        let item = ctx.list().unwrap().remove(0);
+        for name in ["../escaped", "/absolute", "a/b", "a/", "..", ".", ""] {
+            match ctx.restore_to(item.clone(), &destination, Some(OsStr::new(name))) {
+                Err(Error::InvalidName { name: rejected }) => assert_eq!(rejected, name),
+                other => panic!("Expected an InvalidName for {:?}, got {:?}", name, other),
+            }
+        }
+        assert_eq!(ctx.list().unwrap().len(), 1);
+        assert!(!root.join("elsewhere").join("escaped").exists());
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_restore_overwrite_failure() {
+        init_logging();
+        let (ctx, root) = isolated_context();
+        let dir = root.join("dir");
+        std::fs::create_dir_all(dir.join("nested")).unwrap();
+        let item = ctx.delete_returning_item(&dir).unwrap();
+        // A directory can't be moved into itself, so restoring fails after the existing file was
+        // moved aside.
+        let payload = root.join("home/Trash/files/dir");
+        let target = payload.join("nested").join("dir");
+        std::fs::write(&target, b"existing").unwrap();
+        let mut info = TrashInfo::read(Path::new(&item.id)).unwrap();
+        info.path = target.clone();
+        std::fs::write(&item.id, info.to_string()).unwrap();
+        let file = root.join("file");
+        std::fs::write(&file, b"trashed").unwrap();
+        let file_item = ctx.delete_returning_item(&file).unwrap();
+        std::fs::write(&file, b"existing").unwrap();
+
+        let mut items = ctx.list().unwrap();
+        items.sort_by(|a, b| a.name.cmp(&b.name));
+        let options = RestoreOptions { collision_policy: RestoreCollisionPolicy::Overwrite };
+        let outcomes = ctx.restore_all_with_options(items, &options).unwrap();
+        assert!(matches!(outcomes[0].1, RestoreOutcome::Failed(_)));
+        assert!(matches!(outcomes[1].1, RestoreOutcome::Restored { .. }));
+        assert_eq!(std::fs::read(&target).unwrap(), b"existing");
+        assert_eq!(std::fs::read_dir(payload.join("nested")).unwrap().count(), 1);
+        assert_eq!(std::fs::read(&file).unwrap(), b"trashed");
+        assert_eq!(std::fs::read_dir(&root).unwrap().count(), 3);
+        let remaining = ctx.list().unwrap();
+        assert_eq!(remaining.len(), 1);
+        assert_ne!(remaining[0].id, file_item.id);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_directory_sizes() {
+        init_logging();
+        let (ctx, root) = isolated_context();
+        let trash = root.join("home").join("Trash");
+        let dir = root.join("sized dir");
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::write(dir.join("file"), vec![0u8; 5000]).unwrap();
+        let file = root.join("not-a-dir");
+        File::create(&file).unwrap();
+
+        let items = ctx.delete_all_returning_items([&dir, &file]).unwrap();
+        let sizes = read_directory_sizes(&trash).unwrap();
+        assert_eq!(sizes.len(), 1);
+        assert_eq!(sizes[0].name, "sized dir");
+        assert!(sizes[0].size >= 5000);
+        assert_eq!(sizes[0].mtime, Path::new(&items[0].id).metadata().unwrap().mtime());
+        let raw = std::fs::read_to_string(trash.join("directorysizes")).unwrap();
+        assert!(raw.ends_with(" sized%20dir\n"));
+
+        ctx.purge_all(items).unwrap();
+        assert!(read_directory_sizes(&trash).unwrap().is_empty());
+
+        std::fs::create_dir(&dir).unwrap();
+        let item = ctx.delete_returning_item(&dir).unwrap();
+        assert_eq!(read_directory_sizes(&trash).unwrap().len(), 1);
+        ctx.restore_all(vec![item]).unwrap();
+        assert!(read_directory_sizes(&trash).unwrap().is_empty());
+
+        std::fs::write(trash.join("directorysizes"), b"1 2 a\n3 4 \xff\xfe\nnot a line\n5 6 b\n")
+            .unwrap();
+        let names: Vec<_> =
+            read_directory_sizes(&trash).unwrap().into_iter().map(|e| e.name).collect();
+        assert_eq!(names, ["a", "b"]);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_concurrent_directory_size_updates() {
+        init_logging();
+        let (_, root) = isolated_context();
+        let trash = root.join("home").join("Trash");
+        std::fs::create_dir_all(&trash).unwrap();
+        let threads: Vec<_> = (0..8)
+            .map(|thread| {
+                let trash = trash.clone();
+                std::thread::spawn(move || {
+                    for i in 0..20 {
+                        update_directory_sizes(&trash, |entries| {
+                            let name = format!("{}-{}", thread, i).into();
+                            entries.push(DirectorySize { name, size: 1, mtime: 1 });
+                        })
+                        .unwrap();
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(read_directory_sizes(&trash).unwrap().len(), 160);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_metadata() {
+        init_logging();
+        let (ctx, root) = isolated_context();
+        let dir = root.join("dir");
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::write(dir.join("file"), vec![0u8; 5000]).unwrap();
+        let file = root.join("file");
+        std::fs::write(&file, vec![0u8; 10000]).unwrap();
+        let link = root.join("link");
+        std::os::unix::fs::symlink("file", &link).unwrap();
+
+        let items = ctx.delete_all_returning_items([&dir, &file, &link]).unwrap();
+        let dir_metadata = items[0].metadata().unwrap();
+        assert_eq!(dir_metadata.kind, TrashItemKind::Directory);
+        let cached = read_directory_sizes(root.join("home/Trash")).unwrap();
+        assert_eq!(dir_metadata.size, cached[0].size);
+        let file_metadata = items[1].metadata().unwrap();
+        assert_eq!(file_metadata.kind, TrashItemKind::File);
+        assert!(file_metadata.size >= 10000);
+        assert_eq!(items[2].metadata().unwrap().kind, TrashItemKind::Symlink);
+        let sizes: Vec<_> = item_sizes(&items).into_iter().map(Result::unwrap).collect();
+        assert_eq!(
+            sizes,
+            [dir_metadata.size, file_metadata.size, items[2].metadata().unwrap().size]
+        );
+
+        // The size is calculated when the cache is missing
+        std::fs::remove_file(root.join("home/Trash/directorysizes")).unwrap();
+        assert_eq!(items[0].metadata().unwrap(), dir_metadata);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_percent_encoding() {
+        let raw = b"/a b/%25/\xE9t\xC3\xA9~.-_";
+        let encoded = percent_encode(raw);
+        assert_eq!(encoded, "/a%20b/%2525/%E9t%C3%A9~.-_");
+        assert_eq!(percent_decode(&encoded), raw);
+        // Invalid sequences are kept as they are
+        assert_eq!(percent_decode("100%"), b"100%");
+        assert_eq!(percent_decode("%zz%4"), b"%zz%4");
+        assert_eq!(percent_decode("%+1"), b"%+1");
     }
 
     //////////////////////////////////////////////////////////////////////////////////////
@@ -845,8 +3445,3 @@ mod tests {
         DesktopEnvironment::Other
     }
 }
-
-/// Converts a file system error to a crate `Error`
-fn fsys_err_to_unknown<P: AsRef<Path>>(path: P, orig: std::io::Error) -> Error {
-    Error::Unknown { description: format!("Path: '{:?}'. Message: {}", path.as_ref(), orig) }
-}
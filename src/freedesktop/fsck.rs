@@ -0,0 +1,362 @@
+//! Checks the trash folders for inconsistencies and optionally repairs them.
+//!
+//! See [`TrashContextExtFreedesktop::fsck`](super::TrashContextExtFreedesktop::fsck).
+
+use std::{
+    ffi::{OsStr, OsString},
+    fs::{Metadata, Permissions},
+    io,
+    os::unix::fs::{MetadataExt, PermissionsExt},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use log::debug;
+
+use super::{
+    fsys_err, remove_directory_size, trash_folder_topdir, trash_folders, TrashFolderKind,
+    TrashInfo, TrashValidity,
+};
+use crate::{Error, TrashContext};
+
+/// The name of the folder inside a trash folder that receives the files which can't be repaired.
+pub const QUARANTINE_FOLDER: &str = "quarantine";
+
+/// How old the info file of a placeholder must be before the placeholder is considered stale.
+const STALE_PLACEHOLDER_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// How long after its info file a placeholder may have been modified.
+const PLACEHOLDER_WINDOW: Duration = Duration::from_secs(2);
+
+/// An inconsistency found in a trash folder.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Problem {
+    /// A file in the `files` folder that has no info file, so it's not listed. This happens when
+    /// trashing an item is interrupted after the item was moved and its info file was removed by
+    /// another program.
+    ///
+    /// Repairing moves the file into the quarantine folder.
+    OrphanedFile { trash_folder: PathBuf, name: OsString },
+
+    /// An info file whose file in the `files` folder is missing.
+    ///
+    /// Repairing removes the info file.
+    DanglingInfo { trash_folder: PathBuf, info_file: PathBuf },
+
+    /// An info file that can't be parsed. Its item is not listed.
+    ///
+    /// Repairing moves the info file and its file, if there's one, into the quarantine folder.
+    InvalidInfo { trash_folder: PathBuf, info_file: PathBuf, error: Error },
+
+    /// A `$topdir/.Trash-$uid` folder that is not owned by the current user, or that other users
+    /// can access.
+    ///
+    /// Repairing sets the permissions of the folder to `0o700`. The owner can't be repaired.
+    InsecurePermissions { trash_folder: PathBuf, uid: u32, mode: u32 },
+
+    /// An empty file or directory in the `files` folder while the original item still exists.
+    /// This is the placeholder that's left behind when trashing an item is interrupted before the
+    /// item could be moved into the trash.
+    ///
+    /// The placeholder is created right after its info file, while a trashed item keeps the
+    /// modification time it had before it was trashed. So only an empty file or directory that
+    /// was modified within a few seconds after its info file is reported, and only once the info
+    /// file is older than an hour, so that items that are being trashed right now are left alone.
+    ///
+    /// Repairing moves the placeholder and its info file into the quarantine folder. The original
+    /// item is not touched.
+    StalePlaceholder { trash_folder: PathBuf, info_file: PathBuf, original_path: PathBuf },
+}
+impl Problem {
+    /// Returns the trash folder in which the problem was found.
+    pub fn trash_folder(&self) -> &Path {
+        match self {
+            Problem::OrphanedFile { trash_folder, .. }
+            | Problem::DanglingInfo { trash_folder, .. }
+            | Problem::InvalidInfo { trash_folder, .. }
+            | Problem::InsecurePermissions { trash_folder, .. }
+            | Problem::StalePlaceholder { trash_folder, .. } => trash_folder,
+        }
+    }
+}
+
+/// A problem found by [`fsck`](super::TrashContextExtFreedesktop::fsck).
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Finding {
+    pub problem: Problem,
+
+    /// The result of repairing the problem. This is `None` when repairing was not requested.
+    pub repair: Option<Result<(), Error>>,
+}
+
+pub(super) fn fsck(ctx: &TrashContext, repair: bool) -> Result<Vec<Finding>, Error> {
+    let uid = unsafe { libc::getuid() };
+    let mut findings = Vec::new();
+    for folder in trash_folders(ctx)? {
+        // Invalid trash folders are not used at all, so there's nothing to keep consistent.
+        if folder.validity != TrashValidity::Valid {
+            continue;
+        }
+        let mut problems = Vec::new();
+        if folder.kind == TrashFolderKind::User {
+            let metadata = folder.path.metadata().map_err(|e| fsys_err(&folder.path, e))?;
+            let mode = metadata.mode() & 0o7777;
+            if metadata.uid() != uid || mode & 0o077 != 0 {
+                problems.push(Problem::InsecurePermissions {
+                    trash_folder: folder.path.clone(),
+                    uid: metadata.uid(),
+                    mode,
+                });
+            }
+        }
+        check_info_files(&folder.path, &mut problems)?;
+        check_files(&folder.path, &mut problems)?;
+        for problem in problems {
+            let repair = if repair { Some(repair_problem(&problem, uid)) } else { None };
+            findings.push(Finding { problem, repair });
+        }
+    }
+    Ok(findings)
+}
+
+/// Returns the entries of `folder`, or nothing if it doesn't exist.
+fn read_dir(folder: &Path) -> Result<Vec<std::fs::DirEntry>, Error> {
+    match std::fs::read_dir(folder) {
+        Ok(read_dir) => read_dir.collect::<Result<_, _>>().map_err(|e| fsys_err(folder, e)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(fsys_err(folder, e)),
+    }
+}
+
+fn check_info_files(trash_folder: &Path, problems: &mut Vec<Problem>) -> Result<(), Error> {
+    let topdir = trash_folder_topdir(trash_folder);
+    for entry in read_dir(&trash_folder.join("info"))? {
+        let info_file = entry.path();
+        if info_file.extension() != Some(OsStr::new("trashinfo")) {
+            continue;
+        }
+        let file = trash_folder.join("files").join(info_file.file_stem().unwrap());
+        let info = match TrashInfo::read(&info_file) {
+            Ok(info) => info,
+            Err(Error::FileSystem { source, .. }) if source.kind() == io::ErrorKind::NotFound => {
+                debug!("The info file {:?} was removed while checking it", info_file);
+                continue;
+            }
+            Err(error) => {
+                let trash_folder = trash_folder.to_owned();
+                problems.push(Problem::InvalidInfo { trash_folder, info_file, error });
+                continue;
+            }
+        };
+        let metadata = match file.symlink_metadata() {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let trash_folder = trash_folder.to_owned();
+                problems.push(Problem::DanglingInfo { trash_folder, info_file });
+                continue;
+            }
+            Err(e) => return Err(fsys_err(&file, e)),
+        };
+        let is_placeholder = if metadata.is_dir() {
+            std::fs::read_dir(&file).map_err(|e| fsys_err(&file, e))?.next().is_none()
+        } else {
+            metadata.is_file() && metadata.len() == 0
+        };
+        let original_path =
+            if info.path.is_relative() { topdir.join(&info.path) } else { info.path };
+        if is_placeholder
+            && is_stale_placeholder(&metadata, &info_file)
+            && original_path.symlink_metadata().is_ok()
+        {
+            problems.push(Problem::StalePlaceholder {
+                trash_folder: trash_folder.to_owned(),
+                info_file,
+                original_path,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Returns true if the empty `payload` was modified shortly after its `info_file`, like a
+/// placeholder is, and the info file is not recent.
+fn is_stale_placeholder(payload: &Metadata, info_file: &Path) -> bool {
+    let (payload_modified, info_modified) =
+        match (payload.modified(), info_file.metadata().and_then(|m| m.modified())) {
+            (Ok(payload_modified), Ok(info_modified)) => (payload_modified, info_modified),
+            _ => return false,
+        };
+    let is_old = info_modified.elapsed().is_ok_and(|age| age >= STALE_PLACEHOLDER_AGE);
+    let modified_after_info = payload_modified
+        .duration_since(info_modified)
+        .is_ok_and(|delay| delay <= PLACEHOLDER_WINDOW);
+    is_old && modified_after_info
+}
+
+fn check_files(trash_folder: &Path, problems: &mut Vec<Problem>) -> Result<(), Error> {
+    for entry in read_dir(&trash_folder.join("files"))? {
+        let name = entry.file_name();
+        let mut info_name = name.clone();
+        info_name.push(".trashinfo");
+        if trash_folder.join("info").join(info_name).symlink_metadata().is_err() {
+            problems.push(Problem::OrphanedFile { trash_folder: trash_folder.to_owned(), name });
+        }
+    }
+    Ok(())
+}
+
+fn repair_problem(problem: &Problem, uid: u32) -> Result<(), Error> {
+    match problem {
+        Problem::OrphanedFile { trash_folder, name } => {
+            quarantine(trash_folder, &trash_folder.join("files").join(name))
+        }
+        Problem::DanglingInfo { info_file, .. } => {
+            std::fs::remove_file(info_file).map_err(|e| fsys_err(info_file, e))
+        }
+        Problem::InvalidInfo { trash_folder, info_file, .. } => {
+            let file = trash_folder.join("files").join(info_file.file_stem().unwrap());
+            if file.symlink_metadata().is_ok() {
+                quarantine(trash_folder, &file)?;
+            }
+            quarantine(trash_folder, info_file)
+        }
+        Problem::InsecurePermissions { trash_folder, uid: owner, .. } => {
+            if *owner != uid {
+                let source = io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    format!("the trash folder is owned by the user {}", owner),
+                );
+                return Err(fsys_err(trash_folder, source));
+            }
+            std::fs::set_permissions(trash_folder, Permissions::from_mode(0o700))
+                .map_err(|e| fsys_err(trash_folder, e))
+        }
+        Problem::StalePlaceholder { trash_folder, info_file, .. } => {
+            let name = info_file.file_stem().unwrap();
+            let file = trash_folder.join("files").join(name);
+            if let Ok(metadata) = file.symlink_metadata() {
+                quarantine(trash_folder, &file)?;
+                if metadata.is_dir() {
+                    remove_directory_size(trash_folder, name);
+                }
+            }
+            quarantine(trash_folder, info_file)
+        }
+    }
+}
+
+/// Moves `path` into the quarantine folder of the trash folder, appending a number to its name
+/// if it's taken.
+fn quarantine(trash_folder: &Path, path: &Path) -> Result<(), Error> {
+    let folder = trash_folder.join(QUARANTINE_FOLDER);
+    std::fs::create_dir_all(&folder).map_err(|e| fsys_err(&folder, e))?;
+    let name = path.file_name().unwrap();
+    for appendage in 1.. {
+        let mut target_name = name.to_owned();
+        if appendage > 1 {
+            target_name.push(format!(".{}", appendage));
+        }
+        let target = folder.join(target_name);
+        if target.symlink_metadata().is_err() {
+            return std::fs::rename(path, &target).map_err(|e| fsys_err(path, e));
+        }
+    }
+    unreachable!()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{File, Permissions},
+        os::unix::fs::PermissionsExt,
+        time::{Duration, SystemTime},
+    };
+
+    use super::{Problem, QUARANTINE_FOLDER};
+    use crate::{
+        os_limited::TrashContextExtOsLimited,
+        platform::{tests::isolated_context, TrashContextExtFreedesktop, TrashInfo},
+        tests::init_logging,
+    };
+
+    #[test]
+    fn test_fsck() {
+        init_logging();
+        let (ctx, root) = isolated_context();
+        let uid = unsafe { libc::getuid() };
+        let mnt_trash = root.join(format!("mnt/.Trash-{}", uid));
+        let home_trash = root.join("home/Trash");
+        std::fs::write(root.join("mnt/kept"), b"kept").unwrap();
+        std::fs::write(root.join("in-home"), b"in-home").unwrap();
+        ctx.delete_all([root.join("mnt/kept"), root.join("in-home")]).unwrap();
+        assert!(ctx.fsck(false).unwrap().is_empty());
+
+        std::fs::set_permissions(&mnt_trash, Permissions::from_mode(0o755)).unwrap();
+        std::fs::write(home_trash.join("files/orphan"), b"orphan").unwrap();
+        let info = |path: &str| {
+            let path = root.join(path);
+            TrashInfo { path, deletion_date: chrono::Local::now().naive_local() }.to_string()
+        };
+        std::fs::write(home_trash.join("info/gone.trashinfo"), info("gone")).unwrap();
+        std::fs::write(home_trash.join("info/bad.trashinfo"), "[Trash Info]\nPath=/bad\n").unwrap();
+        std::fs::write(home_trash.join("files/bad"), b"bad").unwrap();
+        // Only the empty file that was modified right after its old info file is a placeholder.
+        let hours_ago = SystemTime::now() - Duration::from_secs(2 * 60 * 60);
+        let set_modified = |path: &str, time: SystemTime| {
+            let file = File::options().write(true).open(home_trash.join(path)).unwrap();
+            file.set_modified(time).unwrap();
+        };
+        for (name, info_modified, file_modified) in [
+            ("interrupted", hours_ago, hours_ago + Duration::from_secs(1)),
+            ("empty", hours_ago, hours_ago - Duration::from_secs(60)),
+            ("recent", SystemTime::now(), SystemTime::now()),
+        ] {
+            std::fs::write(root.join(name), name).unwrap();
+            std::fs::write(home_trash.join(format!("info/{}.trashinfo", name)), info(name))
+                .unwrap();
+            File::create(home_trash.join(format!("files/{}", name))).unwrap();
+            set_modified(&format!("info/{}.trashinfo", name), info_modified);
+            set_modified(&format!("files/{}", name), file_modified);
+        }
+
+        let mut findings = ctx.fsck(false).unwrap();
+        findings.sort_by_key(|finding| format!("{:?}", finding.problem));
+        assert_eq!(findings.len(), 5, "{:?}", findings);
+        assert!(findings.iter().all(|finding| finding.repair.is_none()));
+        assert!(
+            matches!(&findings[0].problem, Problem::DanglingInfo { info_file, .. } if info_file.ends_with("gone.trashinfo"))
+        );
+        assert!(matches!(&findings[1].problem, Problem::InsecurePermissions { mode: 0o755, .. }));
+        assert!(
+            matches!(&findings[2].problem, Problem::InvalidInfo { info_file, .. } if info_file.ends_with("bad.trashinfo"))
+        );
+        assert!(
+            matches!(&findings[3].problem, Problem::OrphanedFile { name, .. } if name == "orphan")
+        );
+        assert!(
+            matches!(&findings[4].problem, Problem::StalePlaceholder { original_path, .. } if *original_path == root.join("interrupted"))
+        );
+        assert_eq!(findings[1].problem.trash_folder(), mnt_trash);
+
+        let findings = ctx.fsck(true).unwrap();
+        assert_eq!(findings.len(), 5);
+        for finding in findings {
+            assert!(matches!(finding.repair, Some(Ok(()))), "{:?}", finding);
+        }
+        assert!(ctx.fsck(false).unwrap().is_empty());
+        let quarantine = home_trash.join(QUARANTINE_FOLDER);
+        assert_eq!(std::fs::read(quarantine.join("orphan")).unwrap(), b"orphan");
+        assert_eq!(std::fs::read(quarantine.join("bad")).unwrap(), b"bad");
+        assert!(quarantine.join("bad.trashinfo").exists());
+        assert_eq!(std::fs::read(root.join("interrupted")).unwrap(), b"interrupted");
+        assert!(quarantine.join("interrupted").exists());
+        assert!(quarantine.join("interrupted.trashinfo").exists());
+        assert_eq!(std::fs::metadata(&mnt_trash).unwrap().permissions().mode() & 0o777, 0o700);
+        let mut names: Vec<_> = ctx.list().unwrap().into_iter().map(|item| item.name).collect();
+        names.sort();
+        assert_eq!(names, ["empty", "in-home", "kept", "recent"]);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,567 @@
+//! Watches the trash folders for items that are added or removed.
+//!
+//! See [`TrashContextExtFreedesktop::watch`](super::TrashContextExtFreedesktop::watch).
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    ffi::{CString, OsStr},
+    fs::File,
+    io,
+    os::unix::{
+        ffi::OsStrExt,
+        io::{AsRawFd, FromRawFd, OwnedFd},
+    },
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use log::{debug, warn};
+
+use super::{
+    fsys_err, item_from_info, listed_trash_folders, read_info_entry, trash_folder_topdir, TrashInfo,
+};
+use crate::{Error, TrashContext, TrashItem};
+
+/// The events that are watched in the `info` folder of a trash folder.
+const INFO_MASK: u32 = libc::IN_CLOSE_WRITE
+    | libc::IN_MOVED_TO
+    | libc::IN_DELETE
+    | libc::IN_MOVED_FROM
+    | libc::IN_DELETE_SELF
+    | libc::IN_MOVE_SELF
+    | libc::IN_ONLYDIR;
+
+/// The events that are watched in the folders in which a trash folder may appear.
+const ANCESTOR_MASK: u32 = libc::IN_CREATE
+    | libc::IN_MOVED_TO
+    | libc::IN_DELETE
+    | libc::IN_MOVED_FROM
+    | libc::IN_ATTRIB
+    | libc::IN_DELETE_SELF
+    | libc::IN_MOVE_SELF
+    | libc::IN_ONLYDIR;
+
+/// A change of the items in the trash.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TrashEvent {
+    /// An item was put into the trash, or a trash folder that contains the item appeared, for
+    /// example because a removable drive was mounted.
+    Added(TrashItem),
+
+    /// An item was restored or purged, or the trash folder that contained the item disappeared,
+    /// for example because a removable drive was unmounted.
+    Removed(TrashItem),
+}
+
+/// Reports the items that are added to or removed from the trash folders that
+/// [`list`](crate::os_limited::list) reads, using inotify.
+///
+/// The trash folders are looked up again whenever a file system is mounted or unmounted, or
+/// when a `$topdir/.Trash-$uid` or a home trash folder is created, so the items of trash folders
+/// that appear later are reported as well.
+///
+/// The items that are in the trash when the watcher is created are not reported as added, they
+/// are available from [`items`](Self::items) instead.
+///
+/// Iterating over the watcher blocks until the next event.
+pub struct TrashWatcher {
+    ctx: TrashContext,
+    inotify: OwnedFd,
+    /// `/proc/self/mounts`, which becomes readable with priority when the mounts change. `None`
+    /// when the context uses a fixed list of topdirs.
+    mounts: Option<File>,
+    /// The trash folders whose `info` folder is watched, along with their items.
+    folders: HashMap<PathBuf, WatchedFolder>,
+    /// The existing folders in which a trash folder that's not watched yet may appear.
+    ancestors: HashMap<PathBuf, i32>,
+    /// What each watch descriptor belongs to.
+    watches: HashMap<i32, Watch>,
+    pending: VecDeque<TrashEvent>,
+}
+
+struct WatchedFolder {
+    /// `None` after the kernel removed the watch, for example because the `info` folder was
+    /// removed.
+    wd: Option<i32>,
+    /// The items of the folder by the path of their info file.
+    items: HashMap<PathBuf, TrashItem>,
+}
+
+enum Watch {
+    /// The `info` folder of this trash folder.
+    Info(PathBuf),
+    Ancestor(PathBuf),
+}
+
+impl TrashWatcher {
+    pub(super) fn new(ctx: &TrashContext) -> Result<Self, Error> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(inotify_err(io::Error::last_os_error()));
+        }
+        let inotify = unsafe { OwnedFd::from_raw_fd(fd) };
+        let mounts = match ctx.platform_specific.topdirs {
+            Some(_) => None,
+            None => match File::open("/proc/self/mounts") {
+                Ok(file) => Some(file),
+                Err(e) => {
+                    warn!("Mounted file systems are not watched because {:?}", e);
+                    None
+                }
+            },
+        };
+        let mut watcher = TrashWatcher {
+            ctx: ctx.clone(),
+            inotify,
+            mounts,
+            folders: HashMap::new(),
+            ancestors: HashMap::new(),
+            watches: HashMap::new(),
+            pending: VecDeque::new(),
+        };
+        watcher.rescan()?;
+        // The items that were there already are not news.
+        watcher.pending.clear();
+        Ok(watcher)
+    }
+
+    /// Returns the items that are currently in the watched trash folders, in no particular
+    /// order. This is updated as the events are read.
+    pub fn items(&self) -> impl Iterator<Item = &TrashItem> {
+        self.folders.values().flat_map(|folder| folder.items.values())
+    }
+
+    /// Waits until at least one item was added or removed and returns what happened, in order.
+    /// Returns an empty list if nothing happened within `timeout`. `None` waits indefinitely.
+    pub fn wait(&mut self, timeout: Option<Duration>) -> Result<Vec<TrashEvent>, Error> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        while self.pending.is_empty() {
+            let remaining =
+                deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+            let timeout_ms = match remaining {
+                // Round up so that the deadline has passed when `poll` times out.
+                Some(remaining) => {
+                    remaining.as_micros().div_ceil(1000).min(i32::MAX as u128) as i32
+                }
+                None => -1,
+            };
+            let mut fds = [
+                libc::pollfd { fd: self.inotify.as_raw_fd(), events: libc::POLLIN, revents: 0 },
+                libc::pollfd {
+                    fd: self.mounts.as_ref().map_or(-1, |mounts| mounts.as_raw_fd()),
+                    events: libc::POLLPRI,
+                    revents: 0,
+                },
+            ];
+            let ready =
+                unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout_ms) };
+            if ready < 0 {
+                let error = io::Error::last_os_error();
+                if error.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(inotify_err(error));
+            }
+            if ready == 0 {
+                break;
+            }
+            let mut needs_rescan = fds[1].revents != 0;
+            if fds[0].revents != 0 {
+                needs_rescan |= self.read_events()?;
+            }
+            if needs_rescan {
+                self.rescan()?;
+            }
+        }
+        Ok(self.pending.drain(..).collect())
+    }
+
+    /// Reads the available inotify events and updates the items of the watched folders. Returns
+    /// true if the trash folders must be looked up again.
+    fn read_events(&mut self) -> Result<bool, Error> {
+        let mut needs_rescan = false;
+        let mut buffer = [0u8; 4096];
+        loop {
+            let len = unsafe {
+                libc::read(self.inotify.as_raw_fd(), buffer.as_mut_ptr().cast(), buffer.len())
+            };
+            if len < 0 {
+                let error = io::Error::last_os_error();
+                match error.kind() {
+                    io::ErrorKind::WouldBlock => return Ok(needs_rescan),
+                    io::ErrorKind::Interrupted => continue,
+                    _ => return Err(inotify_err(error)),
+                }
+            }
+            let mut offset = 0;
+            while offset < len as usize {
+                let event: libc::inotify_event =
+                    unsafe { std::ptr::read_unaligned(buffer.as_ptr().add(offset).cast()) };
+                let name_start = offset + std::mem::size_of::<libc::inotify_event>();
+                let name = &buffer[name_start..name_start + event.len as usize];
+                // The name is padded with null bytes.
+                let name = OsStr::from_bytes(name.split(|&b| b == 0).next().unwrap_or_default());
+                needs_rescan |= self.handle_event(&event, name);
+                offset = name_start + event.len as usize;
+            }
+        }
+    }
+
+    /// Returns true if the trash folders must be looked up again.
+    fn handle_event(&mut self, event: &libc::inotify_event, name: &OsStr) -> bool {
+        if event.mask & libc::IN_Q_OVERFLOW != 0 {
+            debug!("The inotify queue overflowed, reading all trash folders again");
+            let folders: Vec<PathBuf> = self.folders.keys().cloned().collect();
+            for folder in folders {
+                self.sync_folder(&folder);
+            }
+            return true;
+        }
+        let self_changed = event.mask
+            & (libc::IN_DELETE_SELF | libc::IN_MOVE_SELF | libc::IN_UNMOUNT | libc::IN_IGNORED)
+            != 0;
+        match self.watches.get(&event.wd) {
+            Some(Watch::Info(folder)) => {
+                let folder = folder.clone();
+                if event.mask & libc::IN_IGNORED != 0 {
+                    self.watches.remove(&event.wd);
+                    if let Some(watched) = self.folders.get_mut(&folder) {
+                        watched.wd = None;
+                    }
+                } else if !self_changed && !name.is_empty() {
+                    self.sync_info_file(&folder, name);
+                }
+                self_changed
+            }
+            Some(Watch::Ancestor(path)) => {
+                if event.mask & libc::IN_IGNORED != 0 {
+                    self.ancestors.remove(path);
+                    self.watches.remove(&event.wd);
+                }
+                // Only folders can turn into trash folders.
+                self_changed || event.mask & libc::IN_ISDIR != 0
+            }
+            // The watch was removed by `rescan`.
+            None => false,
+        }
+    }
+
+    /// Looks up the trash folders, starts and stops watching them, and reports the items of the
+    /// folders that appeared or disappeared.
+    fn rescan(&mut self) -> Result<(), Error> {
+        // A folder may be created between looking it up and watching its parent, so look again
+        // until watching the ancestors didn't miss anything.
+        loop {
+            let listed: HashSet<PathBuf> = listed_trash_folders(&self.ctx)?.into_iter().collect();
+            let folders: Vec<PathBuf> = self.folders.keys().cloned().collect();
+            for folder in folders {
+                let is_watched = self.folders[&folder].wd.is_some();
+                if !listed.contains(&folder) || (!is_watched && !self.watch_info(&folder)) {
+                    self.forget_folder(&folder);
+                } else if !is_watched {
+                    self.sync_folder(&folder);
+                }
+            }
+            for folder in listed {
+                if !self.folders.contains_key(&folder) && self.watch_info(&folder) {
+                    self.sync_folder(&folder);
+                }
+            }
+
+            let mut wanted = HashSet::new();
+            let mut deepest = Vec::new();
+            for (info_folder, top) in self.candidate_info_folders()? {
+                if self.folders.contains_key(info_folder.parent().unwrap()) {
+                    continue;
+                }
+                let ancestors: Vec<&Path> = info_folder
+                    .ancestors()
+                    .filter(|ancestor| ancestor.starts_with(&top) && ancestor.is_dir())
+                    .collect();
+                wanted.extend(ancestors.iter().map(|ancestor| ancestor.to_path_buf()));
+                let first = ancestors.first().map(|a| a.to_path_buf());
+                deepest.push((info_folder.clone(), top, first));
+            }
+            self.update_ancestor_watches(wanted);
+            let missed = deepest.iter().any(|(info_folder, top, deepest)| {
+                deepest_watchable(info_folder, top) != deepest.as_deref()
+            });
+            if !missed {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Returns the `info` folders in which items could appear, and the folder above which no
+    /// folders need to be watched to notice them appearing.
+    fn candidate_info_folders(&self) -> Result<Vec<(PathBuf, PathBuf)>, Error> {
+        let mut result = Vec::new();
+        if let Ok(home_trash) = self.ctx.platform_specific.home_trash() {
+            let info_folder = home_trash.join("info");
+            // The folders above the home trash are not expected to come and go.
+            if let Some(top) = info_folder.ancestors().find(|ancestor| ancestor.is_dir()) {
+                let top = top.to_owned();
+                result.push((info_folder, top));
+            }
+        }
+        if let Some(trash_folder) = self.ctx.platform_specific.explicit_trash_folder() {
+            let info_folder = trash_folder.join("info");
+            if let Some(top) = info_folder.ancestors().find(|ancestor| ancestor.is_dir()) {
+                let top = top.to_owned();
+                result.push((info_folder, top));
+            }
+        }
+        let uid = unsafe { libc::getuid() };
+        for mount_point in self.ctx.platform_specific.mount_points()? {
+            let topdir = mount_point.mnt_dir;
+            let admin_trash = topdir.join(".Trash").join(uid.to_string());
+            result.push((admin_trash.join("info"), topdir.clone()));
+            let user_trash = topdir.join(format!(".Trash-{}", uid));
+            result.push((user_trash.join("info"), topdir));
+        }
+        Ok(result)
+    }
+
+    fn update_ancestor_watches(&mut self, wanted: HashSet<PathBuf>) {
+        let unwanted: Vec<PathBuf> =
+            self.ancestors.keys().filter(|path| !wanted.contains(*path)).cloned().collect();
+        for path in unwanted {
+            let wd = self.ancestors.remove(&path).unwrap();
+            self.remove_watch(wd);
+        }
+        for path in wanted {
+            if self.ancestors.contains_key(&path) {
+                continue;
+            }
+            match self.add_watch(&path, ANCESTOR_MASK) {
+                Ok(wd) => {
+                    self.ancestors.insert(path.clone(), wd);
+                    self.watches.insert(wd, Watch::Ancestor(path));
+                }
+                // Some mount points, like the ones of virtual file systems, can't be watched.
+                Err(e) => debug!("Failed to watch {:?}: {}", path, e),
+            }
+        }
+    }
+
+    /// Starts watching the `info` folder of `folder`. Returns false if that failed, for example
+    /// because the `info` folder doesn't exist.
+    fn watch_info(&mut self, folder: &Path) -> bool {
+        let info_folder = folder.join("info");
+        match self.add_watch(&info_folder, INFO_MASK) {
+            Ok(wd) => {
+                self.watches.insert(wd, Watch::Info(folder.to_owned()));
+                self.folders
+                    .entry(folder.to_owned())
+                    .or_insert_with(|| WatchedFolder { wd: None, items: HashMap::new() })
+                    .wd = Some(wd);
+                true
+            }
+            Err(e) => {
+                debug!("Failed to watch {:?}: {}", info_folder, e);
+                false
+            }
+        }
+    }
+
+    /// Stops watching `folder` and reports its items as removed.
+    fn forget_folder(&mut self, folder: &Path) {
+        if let Some(watched) = self.folders.remove(folder) {
+            if let Some(wd) = watched.wd {
+                self.remove_watch(wd);
+            }
+            self.pending.extend(watched.items.into_values().map(TrashEvent::Removed));
+        }
+    }
+
+    /// Reads all info files of `folder` and reports the difference to the known items.
+    fn sync_folder(&mut self, folder: &Path) {
+        let topdir = trash_folder_topdir(folder);
+        let info_folder = folder.join("info");
+        let mut items = HashMap::new();
+        match std::fs::read_dir(&info_folder) {
+            Ok(read_dir) => {
+                for entry in read_dir {
+                    match read_info_entry(topdir, entry) {
+                        Some(Ok(item)) => {
+                            items.insert(PathBuf::from(&item.id), item);
+                        }
+                        Some(Err(e)) => debug!("Skipping an item of the trash: {}", e),
+                        None => {}
+                    }
+                }
+            }
+            Err(e) => debug!("Failed to read {:?}: {}", info_folder, e),
+        }
+        let watched = self.folders.get_mut(folder).unwrap();
+        let known = std::mem::replace(&mut watched.items, items);
+        for (info_file, item) in known.iter() {
+            if !watched.items.contains_key(info_file) {
+                self.pending.push_back(TrashEvent::Removed(item.clone()));
+            }
+        }
+        for (info_file, item) in watched.items.iter() {
+            if !known.contains_key(info_file) {
+                self.pending.push_back(TrashEvent::Added(item.clone()));
+            }
+        }
+    }
+
+    /// Reads a single info file of `folder` that was written or removed and reports the
+    /// difference to the known item.
+    fn sync_info_file(&mut self, folder: &Path, name: &OsStr) {
+        if Path::new(name).extension() != Some(OsStr::new("trashinfo")) {
+            return;
+        }
+        let info_file = folder.join("info").join(name);
+        // An info file that can't be read or parsed, for example because it's not completely
+        // written yet, is not listed either.
+        let item = TrashInfo::read(&info_file)
+            .and_then(|info| item_from_info(trash_folder_topdir(folder), info_file.clone(), &info))
+            .ok();
+        let watched = self.folders.get_mut(folder).unwrap();
+        match item {
+            Some(item) => {
+                if watched.items.insert(info_file, item.clone()).is_none() {
+                    self.pending.push_back(TrashEvent::Added(item));
+                }
+            }
+            None => {
+                if let Some(item) = watched.items.remove(&info_file) {
+                    self.pending.push_back(TrashEvent::Removed(item));
+                }
+            }
+        }
+    }
+
+    fn add_watch(&self, path: &Path, mask: u32) -> Result<i32, Error> {
+        let c_path =
+            CString::new(path.as_os_str().as_bytes()).map_err(|e| fsys_err(path, e.into()))?;
+        let wd =
+            unsafe { libc::inotify_add_watch(self.inotify.as_raw_fd(), c_path.as_ptr(), mask) };
+        if wd < 0 {
+            return Err(fsys_err(path, io::Error::last_os_error()));
+        }
+        Ok(wd)
+    }
+
+    fn remove_watch(&mut self, wd: i32) {
+        self.watches.remove(&wd);
+        // This fails if the kernel removed the watch already, which is fine.
+        unsafe { libc::inotify_rm_watch(self.inotify.as_raw_fd(), wd) };
+    }
+}
+
+impl Iterator for TrashWatcher {
+    type Item = Result<TrashEvent, Error>;
+
+    /// Blocks until the next item is added or removed.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pending.is_empty() {
+            match self.wait(None) {
+                Ok(events) => self.pending.extend(events),
+                Err(error) => return Some(Err(error)),
+            }
+        }
+        self.pending.pop_front().map(Ok)
+    }
+}
+
+/// Returns the deepest existing folder among the ancestors of `info_folder` that are inside of
+/// `top`. This is `None` when `top` is not a folder, for example when it's a file or it's gone.
+fn deepest_watchable<'a>(info_folder: &'a Path, top: &Path) -> Option<&'a Path> {
+    info_folder.ancestors().find(|ancestor| ancestor.starts_with(top) && ancestor.is_dir())
+}
+
+/// Converts an error of the inotify instance itself to a crate `Error`. The path is the name the
+/// kernel gives to the file descriptor of an inotify instance.
+fn inotify_err(source: io::Error) -> Error {
+    Error::FileSystem { path: PathBuf::from("anon_inode:inotify"), source }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, sync::mpsc, time::Duration};
+
+    use super::{TrashEvent, TrashWatcher};
+    use crate::{
+        os_limited::TrashContextExtOsLimited,
+        platform::{tests::isolated_context, TrashContextExtFreedesktop},
+        tests::init_logging,
+    };
+
+    /// Returns the next event, or panics if there's none within a few seconds.
+    fn next_event(watcher: &mut TrashWatcher, buffered: &mut Vec<TrashEvent>) -> TrashEvent {
+        if buffered.is_empty() {
+            *buffered = watcher.wait(Some(Duration::from_secs(5))).unwrap();
+            buffered.reverse();
+        }
+        buffered.pop().expect("No event was reported")
+    }
+
+    #[test]
+    fn test_watch() {
+        init_logging();
+        let (ctx, root) = isolated_context();
+        let uid = unsafe { libc::getuid() };
+        let existing = root.join("existing");
+        File::create(&existing).unwrap();
+        ctx.delete(&existing).unwrap();
+
+        let mut watcher = ctx.watch().unwrap();
+        let mut buffered = Vec::new();
+        assert_eq!(watcher.items().count(), 1);
+        assert!(watcher.wait(Some(Duration::ZERO)).unwrap().is_empty());
+
+        let file = root.join("file");
+        File::create(&file).unwrap();
+        let item = ctx.delete_returning_item(&file).unwrap();
+        assert_eq!(next_event(&mut watcher, &mut buffered), TrashEvent::Added(item.clone()));
+        ctx.restore_all([item.clone()]).unwrap();
+        assert_eq!(next_event(&mut watcher, &mut buffered), TrashEvent::Removed(item));
+
+        // The `.Trash-$uid` folder of the topdir doesn't exist yet.
+        let in_mnt = root.join("mnt").join("in-mnt");
+        File::create(&in_mnt).unwrap();
+        let item = ctx.delete_returning_item(&in_mnt).unwrap();
+        assert_eq!(next_event(&mut watcher, &mut buffered), TrashEvent::Added(item.clone()));
+        assert_eq!(watcher.items().count(), 2);
+
+        // Like unmounting the file system that contains the trash folder.
+        std::fs::remove_dir_all(root.join("mnt").join(format!(".Trash-{}", uid))).unwrap();
+        assert_eq!(next_event(&mut watcher, &mut buffered), TrashEvent::Removed(item));
+
+        ctx.purge_all(ctx.list().unwrap()).unwrap();
+        assert!(matches!(next_event(&mut watcher, &mut buffered), TrashEvent::Removed(_)));
+        assert_eq!(watcher.items().count(), 0);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_watch_topdir_not_a_directory() {
+        init_logging();
+        let (mut ctx, root) = isolated_context();
+        let file_topdir = root.join("file");
+        File::create(&file_topdir).unwrap();
+        ctx.set_topdirs(Some(vec![root.join("mnt"), file_topdir, root.join("missing")]));
+
+        // Watching must not keep looking for ancestors that can't be watched.
+        let (sender, receiver) = mpsc::channel();
+        let watching_ctx = ctx.clone();
+        std::thread::spawn(move || {
+            let watcher = watching_ctx.watch().map(|watcher| watcher.items().count());
+            sender.send(watcher).unwrap();
+        });
+        let items = receiver.recv_timeout(Duration::from_secs(10)).expect("Watching hangs");
+        assert_eq!(items.unwrap(), 0);
+
+        let mut watcher = ctx.watch().unwrap();
+        let mut buffered = Vec::new();
+        let in_mnt = root.join("mnt").join("in-mnt");
+        File::create(&in_mnt).unwrap();
+        let item = ctx.delete_returning_item(&in_mnt).unwrap();
+        assert_eq!(next_event(&mut watcher, &mut buffered), TrashEvent::Added(item));
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -14,9 +14,13 @@
 //! distribution it runs on, follows this specification.
 //!
 
+use std::borrow::Cow;
 use std::ffi::OsString;
 use std::hash::{Hash, Hasher};
+use std::io;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
 use std::fmt;
 use std::{env::current_dir, error};
@@ -34,22 +38,121 @@ mod platform;
 #[path = "freedesktop.rs"]
 mod platform;
 
+/// The configuration and the formats that are specific to the Freedesktop trash.
+#[cfg(all(unix, not(target_os = "macos"), not(target_os = "ios"), not(target_os = "android")))]
+pub mod freedesktop {
+    #[cfg(target_os = "linux")]
+    pub use crate::platform::watch;
+    pub use crate::platform::{
+        fsck, read_directory_sizes, DirectorySize, TrashContextExtFreedesktop, TrashFolder,
+        TrashFolderKind, TrashInfo, TrashTargetPolicy, TrashValidity,
+    };
+}
+
 #[cfg(target_os = "macos")]
 pub mod macos;
 #[cfg(target_os = "macos")]
 use macos as platform;
 
+#[cfg(feature = "tokio")]
+pub mod nonblocking;
+
 // pub use platform as my_latform;
 pub const DEFAULT_TRASH_CTX: TrashContext = TrashContext::new();
 
 /// A collection of preferences for trash operations.
-#[derive(Clone, Default, Debug)]
+#[derive(Clone, Default)]
 pub struct TrashContext {
     platform_specific: platform::PlatformTrashContext,
+    progress_observer: Option<Arc<dyn ProgressObserver>>,
+    cancellation_token: Option<CancellationToken>,
+}
+impl fmt::Debug for TrashContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TrashContext")
+            .field("platform_specific", &self.platform_specific)
+            .field("progress_observer", &self.progress_observer.as_ref().map(|_| "..."))
+            .field("cancellation_token", &self.cancellation_token)
+            .finish()
+    }
 }
 impl TrashContext {
     pub const fn new() -> Self {
-        Self { platform_specific: platform::PlatformTrashContext::new() }
+        Self {
+            platform_specific: platform::PlatformTrashContext::new(),
+            progress_observer: None,
+            cancellation_token: None,
+        }
+    }
+
+    /// Sets the observer that is notified about the progress of long running operations.
+    ///
+    /// Trashing, purging, and restoring items report the progress after every item, and while
+    /// copying or removing the entries of a directory. Progress is currently only reported on
+    /// Freedesktop systems.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::sync::Arc;
+    /// use trash::{Progress, TrashContext};
+    /// let mut trash_ctx = TrashContext::default();
+    /// trash_ctx.set_progress_observer(Some(Arc::new(|progress: &Progress| {
+    ///     println!("{} bytes so far", progress.bytes_processed);
+    /// })));
+    /// ```
+    pub fn set_progress_observer(&mut self, observer: Option<Arc<dyn ProgressObserver>>) {
+        self.progress_observer = observer;
+    }
+    pub fn progress_observer(&self) -> Option<&Arc<dyn ProgressObserver>> {
+        self.progress_observer.as_ref()
+    }
+
+    /// Sets the token that cancels the operations of this context.
+    ///
+    /// Trashing, purging, and restoring items check the token before every item, and on
+    /// Freedesktop systems also while copying or removing the entries of a directory. A cancelled
+    /// operation returns [`Error::Cancelled`]. The items that were processed before the
+    /// cancellation stay processed, while the item that was being processed is left where it was:
+    /// a partial copy is rolled back and a directory that was partially purged stays in the trash.
+    ///
+    /// On Windows, trashing, purging, and `os_limited::restore_all` hand all items over to the
+    /// shell in a single operation. The token is checked while the items are collected, so a
+    /// cancellation either stops the operation before anything happened or comes too late.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::fs::File;
+    /// use trash::{CancellationToken, Error, TrashContext};
+    /// let token = CancellationToken::new();
+    /// let mut trash_ctx = TrashContext::default();
+    /// trash_ctx.set_cancellation_token(Some(token.clone()));
+    /// File::create("cancelled-example").unwrap();
+    /// token.cancel();
+    /// assert!(matches!(trash_ctx.delete("cancelled-example"), Err(Error::Cancelled)));
+    /// std::fs::remove_file("cancelled-example").unwrap();
+    /// ```
+    pub fn set_cancellation_token(&mut self, token: Option<CancellationToken>) {
+        self.cancellation_token = token;
+    }
+    pub fn cancellation_token(&self) -> Option<&CancellationToken> {
+        self.cancellation_token.as_ref()
+    }
+
+    /// Returns [`Error::Cancelled`] if the cancellation token of this context was cancelled.
+    pub(crate) fn check_cancelled(&self) -> Result<(), Error> {
+        match &self.cancellation_token {
+            Some(token) if token.is_cancelled() => Err(Error::Cancelled),
+            _ => Ok(()),
+        }
+    }
+
+    #[allow(dead_code)]
+    pub(crate) fn report_progress(&self, progress: &Progress) {
+        if let Some(observer) = &self.progress_observer {
+            observer.on_progress(progress);
+        }
     }
 
     /// Removes a single file or directory.
@@ -94,8 +197,38 @@ impl TrashContext {
         trace!("Starting canonicalize_paths");
         let full_paths = canonicalize_paths(paths)?;
         trace!("Finished canonicalize_paths");
+        self.check_cancelled()?;
         self.delete_all_canonicalized(full_paths)
     }
+
+    /// Removes the files/directories one by one and reports the outcome for each of them, in the
+    /// order they were provided. The paths in the report are the ones that were provided.
+    ///
+    /// With [`BatchMode::StopOnError`] the remaining paths are put into `not_attempted` after the
+    /// first failure, while [`BatchMode::ContinueOnError`] attempts all of them.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::fs::File;
+    /// use trash::{delete_all_with_report, BatchMode};
+    /// File::create("delete_me_reported").unwrap();
+    /// let report =
+    ///     delete_all_with_report(&["delete_me_reported", "does_not_exist"], BatchMode::ContinueOnError);
+    /// assert_eq!(report.succeeded.len(), 1);
+    /// assert_eq!(report.failed.len(), 1);
+    /// ```
+    pub fn delete_all_with_report<I, T>(&self, paths: I, mode: BatchMode) -> BatchReport<PathBuf>
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<Path>,
+    {
+        run_batch(paths.into_iter().map(|path| path.as_ref().to_owned()), mode, |path| {
+            let full_paths = canonicalize_paths([path])?;
+            self.check_cancelled()?;
+            self.delete_all_canonicalized(full_paths)
+        })
+    }
 }
 
 /// Convenience method for `DEFAULT_TRASH_CTX.delete()`.
@@ -116,10 +249,165 @@ where
     DEFAULT_TRASH_CTX.delete_all(paths)
 }
 
+/// Convenience method for `DEFAULT_TRASH_CTX.delete_all_with_report()`.
+///
+/// See: [`TrashContext::delete_all_with_report`](TrashContext::delete_all_with_report)
+pub fn delete_all_with_report<I, T>(paths: I, mode: BatchMode) -> BatchReport<PathBuf>
+where
+    I: IntoIterator<Item = T>,
+    T: AsRef<Path>,
+{
+    DEFAULT_TRASH_CTX.delete_all_with_report(paths, mode)
+}
+
+/// Determines how an operation on multiple items proceeds after one of the items failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BatchMode {
+    /// Don't attempt the remaining items after a failure.
+    ///
+    /// This is the default.
+    StopOnError,
+
+    /// Attempt every item regardless of failures.
+    ContinueOnError,
+}
+impl BatchMode {
+    /// Returns `BatchMode::StopOnError`
+    pub const fn new() -> Self {
+        BatchMode::StopOnError
+    }
+}
+impl Default for BatchMode {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The outcome of an operation on multiple items. Every provided item is in exactly one of the
+/// lists, in the order the items were provided.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BatchReport<T> {
+    /// The items that were processed successfully.
+    pub succeeded: Vec<T>,
+
+    /// The items that failed, along with the reason.
+    pub failed: Vec<(T, Error)>,
+
+    /// The items that were not attempted because an earlier item failed and the mode was
+    /// [`BatchMode::StopOnError`].
+    pub not_attempted: Vec<T>,
+}
+impl<T> BatchReport<T> {
+    /// Returns true if every item was processed successfully.
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty() && self.not_attempted.is_empty()
+    }
+}
+
+/// Calls `op` on each of the `items` and collects the outcomes according to `mode`.
+fn run_batch<T, I, F>(items: I, mode: BatchMode, mut op: F) -> BatchReport<T>
+where
+    I: IntoIterator<Item = T>,
+    F: FnMut(&T) -> Result<(), Error>,
+{
+    let mut report =
+        BatchReport { succeeded: Vec::new(), failed: Vec::new(), not_attempted: Vec::new() };
+    let mut iter = items.into_iter();
+    while let Some(item) = iter.next() {
+        match op(&item) {
+            Ok(()) => report.succeeded.push(item),
+            Err(error) => {
+                report.failed.push((item, error));
+                if mode == BatchMode::StopOnError {
+                    report.not_attempted.extend(iter);
+                    break;
+                }
+            }
+        }
+    }
+    report
+}
+
+/// The kind of operation that reports a [`Progress`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TrashOperation {
+    /// Putting items into the trash.
+    Delete,
+
+    /// Removing items from the trash permanently.
+    Purge,
+
+    /// Restoring items from the trash.
+    Restore,
+}
+
+/// A snapshot of the progress of an operation. See [`ProgressObserver`].
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct Progress<'a> {
+    pub operation: TrashOperation,
+
+    /// The number of items that were completely processed so far, for example the number of paths
+    /// that were put into the trash.
+    pub items_processed: u64,
+
+    /// The number of files, directories, and symbolic links that were copied or removed so far.
+    /// Items that are simply renamed, like the ones that are trashed on the same device, don't
+    /// contribute to this.
+    pub entries_processed: u64,
+
+    /// The number of bytes that were copied or removed so far.
+    pub bytes_processed: u64,
+
+    /// The path of the most recently processed item, file, directory, or symbolic link.
+    pub current_path: &'a Path,
+}
+
+/// Receives progress reports from the operations of a [`TrashContext`].
+///
+/// This is implemented for every `Fn(&Progress)` closure that is `Send` and `Sync`.
+pub trait ProgressObserver: Send + Sync {
+    fn on_progress(&self, progress: &Progress);
+}
+impl<F> ProgressObserver for F
+where
+    F: Fn(&Progress) + Send + Sync,
+{
+    fn on_progress(&self, progress: &Progress) {
+        self(progress)
+    }
+}
+
+/// Cancels the operations of the [`TrashContext`]s that it's set on when
+/// [`cancel`](Self::cancel) is called, possibly from another thread.
+///
+/// See [`TrashContext::set_cancellation_token`].
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels the operations that are running or that will be started with this token. A
+    /// cancelled token can't be reset, create a new one instead.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
 ///
 /// Provides information about an error.
 ///
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Error {
     Unknown {
         description: String,
@@ -139,6 +427,19 @@ pub enum Error {
     CanonicalizePath {
         /// Path that triggered the error.
         original: PathBuf,
+        /// The error returned by the operating system.
+        #[cfg_attr(feature = "serde", serde(with = "serde_io_error"))]
+        source: io::Error,
+    },
+
+    /// A file system operation failed on `path`. Use `source.kind()` to find out why, for
+    /// example whether the permission was denied or the path was not found.
+    FileSystem {
+        /// Path that triggered the error.
+        path: PathBuf,
+        /// The error returned by the operating system.
+        #[cfg_attr(feature = "serde", serde(with = "serde_io_error"))]
+        source: io::Error,
     },
 
     /// Error while converting an [`OsString`] to a [`String`].
@@ -179,13 +480,121 @@ pub enum Error {
         path: PathBuf,
         items: Vec<TrashItem>,
     },
+
+    /// The operation was cancelled through the [`CancellationToken`] of the [`TrashContext`].
+    /// See [`TrashContext::set_cancellation_token`].
+    Cancelled,
+
+    /// A `.trashinfo` file or the item it describes is invalid.
+    ///
+    /// `path`: The path of the `.trashinfo` file. It's empty when the contents were parsed with
+    /// [`freedesktop::TrashInfo::parse`] directly.
+    ///
+    /// `reason`: What's wrong with it.
+    InvalidTrashInfo {
+        path: PathBuf,
+        reason: String,
+    },
+
+    /// The home trash couldn't be located, because neither the `XDG_DATA_HOME` nor the `HOME`
+    /// environment variable is set.
+    NoHomeTrash,
+
+    /// The path is not one of the trash folders of the current user.
+    ///
+    /// `path`: The rejected path.
+    NotATrashFolder {
+        path: PathBuf,
+    },
+
+    /// A name that was expected to be a single file name contained a separator, or was empty,
+    /// `.`, `..`, or an absolute path.
+    ///
+    /// `name`: The rejected name.
+    InvalidName {
+        name: OsString,
+    },
 }
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "Error during a `trash` operation: {:?}", self)
     }
 }
-impl error::Error for Error {}
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::CanonicalizePath { source, .. } | Error::FileSystem { source, .. } => {
+                Some(source)
+            }
+            _ => None,
+        }
+    }
+}
+/// Serializes an `io::Error` as its OS error code, its kind, and its message. The kind is stored by
+/// the name of the `io::ErrorKind` variant. Kinds that are too new to be known here deserialize as
+/// `io::ErrorKind::Other`, while errors that come from the operating system are restored from
+/// their code.
+#[cfg(feature = "serde")]
+mod serde_io_error {
+    use std::io::{self, ErrorKind};
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    struct IoError {
+        raw_os_error: Option<i32>,
+        // Older versions of this crate did not serialize the kind.
+        #[serde(default)]
+        kind: Option<String>,
+        message: String,
+    }
+
+    const KINDS: &[ErrorKind] = &[
+        ErrorKind::NotFound,
+        ErrorKind::PermissionDenied,
+        ErrorKind::ConnectionRefused,
+        ErrorKind::ConnectionReset,
+        ErrorKind::ConnectionAborted,
+        ErrorKind::NotConnected,
+        ErrorKind::AddrInUse,
+        ErrorKind::AddrNotAvailable,
+        ErrorKind::BrokenPipe,
+        ErrorKind::AlreadyExists,
+        ErrorKind::WouldBlock,
+        ErrorKind::InvalidInput,
+        ErrorKind::InvalidData,
+        ErrorKind::TimedOut,
+        ErrorKind::WriteZero,
+        ErrorKind::Interrupted,
+        ErrorKind::Unsupported,
+        ErrorKind::UnexpectedEof,
+        ErrorKind::OutOfMemory,
+        ErrorKind::Other,
+    ];
+
+    pub fn serialize<S: Serializer>(error: &io::Error, serializer: S) -> Result<S::Ok, S::Error> {
+        IoError {
+            raw_os_error: error.raw_os_error(),
+            kind: Some(format!("{:?}", error.kind())),
+            message: error.to_string(),
+        }
+        .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<io::Error, D::Error> {
+        let error = IoError::deserialize(deserializer)?;
+        if let Some(code) = error.raw_os_error {
+            return Ok(io::Error::from_raw_os_error(code));
+        }
+        let kind = KINDS
+            .iter()
+            .copied()
+            .find(|kind| error.kind.as_deref() == Some(&format!("{:?}", kind)))
+            .unwrap_or(ErrorKind::Other);
+        Ok(io::Error::new(kind, error.message))
+    }
+}
+
 pub fn into_unknown<E: std::fmt::Display>(err: E) -> Error {
     Error::Unknown { description: format!("{}", err) }
 }
@@ -200,17 +609,16 @@ where
         .map(|x| {
             let target_ref = x.as_ref();
             let target = if target_ref.is_relative() {
-                let curr_dir = current_dir().map_err(|_| Error::CouldNotAccess {
-                    target: "[Current working directory]".into(),
-                })?;
+                let curr_dir = current_dir()
+                    .map_err(|source| Error::FileSystem { path: PathBuf::from("."), source })?;
                 curr_dir.join(target_ref)
             } else {
                 target_ref.to_owned()
             };
             let parent = target.parent().ok_or(Error::TargetedRoot)?;
-            let canonical_parent = parent
-                .canonicalize()
-                .map_err(|_| Error::CanonicalizePath { original: parent.to_owned() })?;
+            let canonical_parent = parent.canonicalize().map_err(|source| {
+                Error::CanonicalizePath { original: parent.to_owned(), source }
+            })?;
             if let Some(file_name) = target.file_name() {
                 Ok(canonical_parent.join(file_name))
             } else {
@@ -229,7 +637,8 @@ where
 ///
 /// A trahs item can be a file or folder or any other object that the target
 /// operating system allows to put into the trash.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TrashItem {
     /// A system specific identifier of the item in the trash.
     ///
@@ -242,7 +651,9 @@ pub struct TrashItem {
 
     /// The name of the item. For example if the folder '/home/user/New Folder'
     /// was deleted, its `name` is 'New Folder'
-    pub name: String,
+    ///
+    /// The name is not necessarily valid Unicode. Use `name_lossy` to display it.
+    pub name: OsString,
 
     /// The path to the parent folder of this item before it was put inside the
     /// trash. For example if the folder '/home/user/New Folder' is in the
@@ -257,6 +668,34 @@ pub struct TrashItem {
     pub time_deleted: i64,
 }
 
+/// The kind of a [`TrashItem`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TrashItemKind {
+    File,
+    Directory,
+    Symlink,
+
+    /// Anything else, for example a named pipe or a socket.
+    Other,
+}
+
+/// Information about a [`TrashItem`] that is obtained from the file system.
+///
+/// See [`TrashItem::metadata`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TrashItemMetadata {
+    pub kind: TrashItemKind,
+
+    /// The number of bytes the item occupies. For directories this includes the size of
+    /// everything inside the directory, recursively.
+    ///
+    /// On Linux this is the disk space used by the item, as reported by `du -B1`. On Windows this
+    /// is the length of the file or the total length of the files in the directory.
+    pub size: u64,
+}
+
 /// Platform independent functions of `TrashItem`.
 ///
 /// See `TrahsItemPlatformDep` for platform dependent functions.
@@ -266,6 +705,66 @@ impl TrashItem {
     pub fn original_path(&self) -> PathBuf {
         self.original_parent.join(&self.name)
     }
+
+    /// Returns the `name` of the item, replacing any invalid Unicode sequences with
+    /// `U+FFFD REPLACEMENT CHARACTER`.
+    pub fn name_lossy(&self) -> Cow<'_, str> {
+        self.name.to_string_lossy()
+    }
+}
+#[cfg(any(
+    target_os = "windows",
+    all(unix, not(target_os = "macos"), not(target_os = "ios"), not(target_os = "android"))
+))]
+impl TrashItem {
+    /// Returns the kind and the size of the item as it is stored in the trash.
+    ///
+    /// This is only available on Windows and on Freedesktop compliant systems.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::fs::File;
+    /// use trash::{delete, os_limited::{list, purge_all}, TrashItemKind};
+    /// let filename = "trash-metadata-example";
+    /// File::create(filename).unwrap();
+    /// delete(filename).unwrap();
+    /// let item = list().unwrap().into_iter().find(|x| x.name == filename).unwrap();
+    /// assert_eq!(item.metadata().unwrap().kind, TrashItemKind::File);
+    /// purge_all(vec![item]).unwrap();
+    /// ```
+    pub fn metadata(&self) -> Result<TrashItemMetadata, Error> {
+        platform::metadata(self)
+    }
+
+    /// Returns the item of the trash whose `id` is the given one, for example to rebuild an item
+    /// that was stored or sent to another process earlier.
+    ///
+    /// On Linux the `id` must be the path of an info file in one of the trash folders of the
+    /// current user. An [`Error::CouldNotAccess`] is returned if the item is not in the trash
+    /// anymore.
+    ///
+    /// This uses [`DEFAULT_TRASH_CTX`], see
+    /// [`os_limited::TrashContextExtOsLimited::item_from_id`] to use another context.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::fs::File;
+    /// use trash::{delete, os_limited::{list, purge_all}, TrashItem};
+    /// let filename = "trash-from_id-example";
+    /// File::create(filename).unwrap();
+    /// delete(filename).unwrap();
+    /// let item = list().unwrap().into_iter().find(|x| x.name == filename).unwrap();
+    /// let stored_id = item.id.clone();
+    /// assert_eq!(TrashItem::from_id(stored_id.clone()).unwrap(), item);
+    /// purge_all(vec![item]).unwrap();
+    /// assert!(TrashItem::from_id(stored_id).is_err());
+    /// ```
+    pub fn from_id(id: impl Into<OsString>) -> Result<TrashItem, Error> {
+        use os_limited::TrashContextExtOsLimited;
+        DEFAULT_TRASH_CTX.item_from_id(&id.into())
+    }
 }
 impl PartialEq for TrashItem {
     fn eq(&self, other: &Self) -> bool {
@@ -289,20 +788,437 @@ pub mod os_limited {
 
     use std::{
         collections::HashSet,
+        ffi::{OsStr, OsString},
         hash::{Hash, Hasher},
+        path::{Path, PathBuf},
+        sync::atomic::{AtomicUsize, Ordering},
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    };
+
+    use super::{
+        platform, run_batch, BatchMode, BatchReport, Error, TrashContext, TrashItem,
+        DEFAULT_TRASH_CTX,
     };
 
-    use super::{platform, Error, TrashItem};
+    mod query;
+    pub use query::{SortBy, SortOrder, TrashQuery};
 
+    /// Functions of [`TrashContext`] that are only available on Windows and on Freedesktop
+    /// compliant systems.
+    ///
+    /// The free functions of this module are convenience methods that call these on
+    /// [`DEFAULT_TRASH_CTX`].
     pub trait TrashContextExtOsLimited {
-        fn list() -> Result<Vec<TrashItem>, Error>;
-        fn purge_all<I>(items: I) -> Result<(), Error>
+        /// See: [`list`]
+        fn list(&self) -> Result<Vec<TrashItem>, Error>;
+
+        /// See: [`list_iter`]
+        fn list_iter(&self) -> Result<ListIter, Error>;
+
+        /// See: [`TrashItem::from_id`]
+        fn item_from_id(&self, id: &OsStr) -> Result<TrashItem, Error>;
+
+        /// See: [`query`]
+        fn query(&self, query: &TrashQuery) -> Result<Vec<TrashItem>, Error>;
+
+        /// See: [`purge_all`]
+        fn purge_all<I>(&self, items: I) -> Result<(), Error>
         where
             I: IntoIterator<Item = TrashItem>;
 
-        fn restore_all<I>(items: I) -> Result<(), Error>
+        /// See: [`restore_all`]
+        fn restore_all<I>(&self, items: I) -> Result<(), Error>
         where
             I: IntoIterator<Item = TrashItem>;
+
+        /// See: [`restore_all_with_options`]
+        fn restore_all_with_options<I>(
+            &self,
+            items: I,
+            options: &RestoreOptions,
+        ) -> Result<Vec<(TrashItem, RestoreOutcome)>, Error>
+        where
+            I: IntoIterator<Item = TrashItem>;
+
+        /// See: [`restore_to`]
+        fn restore_to<P: AsRef<Path>>(
+            &self,
+            item: TrashItem,
+            destination: P,
+            new_name: Option<&OsStr>,
+        ) -> Result<PathBuf, Error>;
+
+        /// See: [`purge_all_with_report`]
+        fn purge_all_with_report<I>(&self, items: I, mode: BatchMode) -> BatchReport<TrashItem>
+        where
+            I: IntoIterator<Item = TrashItem>;
+
+        /// See: [`restore_all_with_report`]
+        fn restore_all_with_report<I>(&self, items: I, mode: BatchMode) -> BatchReport<TrashItem>
+        where
+            I: IntoIterator<Item = TrashItem>;
+
+        /// See: [`expire`]
+        fn expire(&self, max_age: Duration, dry_run: bool) -> Result<ExpiryReport, Error>;
+
+        /// See: [`enforce_quota`]
+        fn enforce_quota(&self, max_bytes: u64) -> Result<QuotaReport, Error>;
+
+        /// See: [`empty`]
+        fn empty(&self) -> Result<(), Error>;
+    }
+    impl TrashContextExtOsLimited for TrashContext {
+        fn list(&self) -> Result<Vec<TrashItem>, Error> {
+            platform::list(self)
+        }
+
+        fn list_iter(&self) -> Result<ListIter, Error> {
+            Ok(ListIter { inner: platform::list_iter(self)? })
+        }
+
+        fn item_from_id(&self, id: &OsStr) -> Result<TrashItem, Error> {
+            platform::item_from_id(self, id)
+        }
+
+        fn query(&self, query: &TrashQuery) -> Result<Vec<TrashItem>, Error> {
+            query.run(self)
+        }
+
+        fn purge_all<I>(&self, items: I) -> Result<(), Error>
+        where
+            I: IntoIterator<Item = TrashItem>,
+        {
+            self.check_cancelled()?;
+            platform::purge_all(self, items)
+        }
+
+        fn restore_all<I>(&self, items: I) -> Result<(), Error>
+        where
+            I: IntoIterator<Item = TrashItem>,
+        {
+            // Check for twins here cause that's pretty platform independent.
+            struct ItemWrapper<'a>(&'a TrashItem);
+            impl<'a> PartialEq for ItemWrapper<'a> {
+                fn eq(&self, other: &Self) -> bool {
+                    self.0.original_path() == other.0.original_path()
+                }
+            }
+            impl<'a> Eq for ItemWrapper<'a> {}
+            impl<'a> Hash for ItemWrapper<'a> {
+                fn hash<H: Hasher>(&self, state: &mut H) {
+                    self.0.original_path().hash(state);
+                }
+            }
+            let items = items.into_iter().collect::<Vec<_>>();
+            let mut item_set = HashSet::with_capacity(items.len());
+            for item in items.iter() {
+                if !item_set.insert(ItemWrapper(item)) {
+                    return Err(Error::RestoreTwins { path: item.original_path(), items });
+                }
+            }
+            self.check_cancelled()?;
+            platform::restore_all(self, items)
+        }
+
+        fn restore_all_with_options<I>(
+            &self,
+            items: I,
+            options: &RestoreOptions,
+        ) -> Result<Vec<(TrashItem, RestoreOutcome)>, Error>
+        where
+            I: IntoIterator<Item = TrashItem>,
+        {
+            self.check_cancelled()?;
+            platform::restore_all_with_options(self, items, options)
+        }
+
+        fn restore_to<P: AsRef<Path>>(
+            &self,
+            item: TrashItem,
+            destination: P,
+            new_name: Option<&OsStr>,
+        ) -> Result<PathBuf, Error> {
+            if let Some(name) = new_name {
+                // `components` skips trailing separators and `.`, which must not pass either.
+                let mut components = Path::new(name).components();
+                match (components.next(), components.next()) {
+                    (Some(std::path::Component::Normal(single)), None) if single == name => {}
+                    _ => return Err(Error::InvalidName { name: name.to_owned() }),
+                }
+            }
+            platform::restore_to(self, item, destination.as_ref(), new_name)
+        }
+
+        fn purge_all_with_report<I>(&self, items: I, mode: BatchMode) -> BatchReport<TrashItem>
+        where
+            I: IntoIterator<Item = TrashItem>,
+        {
+            run_batch(items, mode, |item| {
+                self.check_cancelled()?;
+                platform::purge_all(self, std::iter::once(item.clone()))
+            })
+        }
+
+        fn restore_all_with_report<I>(&self, items: I, mode: BatchMode) -> BatchReport<TrashItem>
+        where
+            I: IntoIterator<Item = TrashItem>,
+        {
+            let options = RestoreOptions { collision_policy: RestoreCollisionPolicy::Fail };
+            run_batch(items, mode, |item| {
+                self.check_cancelled()?;
+                let outcomes = platform::restore_all_with_options(
+                    self,
+                    std::iter::once(item.clone()),
+                    &options,
+                )?;
+                match outcomes.into_iter().next().map(|(_, outcome)| outcome) {
+                    Some(RestoreOutcome::Restored { .. }) | None => Ok(()),
+                    Some(RestoreOutcome::Collision { path }) => {
+                        Err(Error::RestoreCollision { path, remaining_items: Vec::new() })
+                    }
+                    Some(RestoreOutcome::Failed(error)) => Err(error),
+                    Some(outcome) => unreachable!("{:?} with the `Fail` collision policy", outcome),
+                }
+            })
+        }
+
+        fn expire(&self, max_age: Duration, dry_run: bool) -> Result<ExpiryReport, Error> {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+            let cutoff = now.saturating_sub(max_age.as_secs()).min(i64::MAX as u64) as i64;
+            let items = self.query(&TrashQuery::new().deleted_before(cutoff))?;
+            let mut failed = Vec::new();
+            let mut sized_items = Vec::new();
+            for (item, size) in self::sized_items(items) {
+                match size {
+                    Ok(size) => sized_items.push((item, size)),
+                    // Counting the item as 0 bytes would make `bytes` silently under-report.
+                    Err(error) => failed.push((item, error)),
+                }
+            }
+            if dry_run {
+                let bytes = sized_items.iter().map(|(_, size)| size).sum();
+                let expired = sized_items.into_iter().map(|(item, _)| item).collect();
+                return Ok(ExpiryReport { expired, bytes, failed });
+            }
+            let report = run_batch(sized_items, BatchMode::ContinueOnError, |(item, _)| {
+                self.check_cancelled()?;
+                platform::purge_all(self, std::iter::once(item.clone()))
+            });
+            failed.extend(report.failed.into_iter().map(|((item, _), error)| (item, error)));
+            Ok(ExpiryReport {
+                bytes: report.succeeded.iter().map(|(_, size)| size).sum(),
+                expired: report.succeeded.into_iter().map(|(item, _)| item).collect(),
+                failed,
+            })
+        }
+
+        fn enforce_quota(&self, max_bytes: u64) -> Result<QuotaReport, Error> {
+            evict_oldest(self, self.list()?, max_bytes)
+        }
+
+        fn empty(&self) -> Result<(), Error> {
+            platform::empty(self)
+        }
+    }
+
+    /// The result of [`enforce_quota`].
+    #[derive(Debug)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct QuotaReport {
+        /// The items that were removed permanently, oldest first.
+        pub evicted: Vec<TrashItem>,
+
+        /// The sum of the sizes of the `evicted` items.
+        pub bytes_freed: u64,
+
+        /// The sum of the sizes of the items that are left in the trash.
+        pub bytes_remaining: u64,
+
+        /// The items that should have been evicted but could not be removed, along with the
+        /// reason. Their size is included in `bytes_remaining`. The items whose size could not be
+        /// determined are in here as well; these are never evicted and not included in any of the
+        /// sums.
+        pub failed: Vec<(TrashItem, Error)>,
+    }
+
+    /// Pairs each of the `items` with its size as reported by [`TrashItem::metadata`].
+    fn sized_items(items: Vec<TrashItem>) -> Vec<(TrashItem, Result<u64, Error>)> {
+        let sizes = platform::item_sizes(&items);
+        items.into_iter().zip(sizes).collect()
+    }
+
+    /// Purges the least recently deleted of the `items` until the total size of the remaining
+    /// ones is at most `max_bytes`.
+    pub(crate) fn evict_oldest(
+        ctx: &TrashContext,
+        items: Vec<TrashItem>,
+        max_bytes: u64,
+    ) -> Result<QuotaReport, Error> {
+        let mut report = QuotaReport {
+            evicted: Vec::new(),
+            bytes_freed: 0,
+            bytes_remaining: 0,
+            failed: Vec::new(),
+        };
+        let mut sized_items = Vec::new();
+        for (item, size) in self::sized_items(items) {
+            match size {
+                Ok(size) => sized_items.push((item, size)),
+                // Without a size it's unknown whether evicting the item would help.
+                Err(error) => report.failed.push((item, error)),
+            }
+        }
+        sized_items.sort_by(|(a, _), (b, _)| {
+            a.time_deleted.cmp(&b.time_deleted).then_with(|| a.id.cmp(&b.id))
+        });
+        report.bytes_remaining = sized_items.iter().map(|(_, size)| size).sum();
+        for (item, size) in sized_items {
+            if report.bytes_remaining <= max_bytes {
+                break;
+            }
+            match ctx
+                .check_cancelled()
+                .and_then(|_| platform::purge_all(ctx, std::iter::once(item.clone())))
+            {
+                Ok(()) => {
+                    report.bytes_freed += size;
+                    report.bytes_remaining -= size;
+                    report.evicted.push(item);
+                }
+                Err(error) => report.failed.push((item, error)),
+            }
+        }
+        Ok(report)
+    }
+
+    /// The result of [`expire`].
+    #[derive(Debug)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct ExpiryReport {
+        /// The items that were deleted before the cutoff. After a dry run these are still in the
+        /// trash, otherwise they have been removed permanently.
+        pub expired: Vec<TrashItem>,
+
+        /// The sum of the sizes of the `expired` items as reported by [`TrashItem::metadata`].
+        /// This is the number of bytes that were freed, or would be freed in case of a dry run.
+        pub bytes: u64,
+
+        /// The items that were deleted before the cutoff but could not be removed, along with the
+        /// reason. Items whose size can't be determined are reported here and left in the trash,
+        /// also after a dry run.
+        pub failed: Vec<(TrashItem, Error)>,
+    }
+
+    /// Determines what happens when the original location of an item that's being restored is
+    /// already taken.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub enum RestoreCollisionPolicy {
+        /// Leave the item in the trash and don't attempt to restore any further items.
+        ///
+        /// This is the default.
+        Fail,
+
+        /// Permanently remove whatever is at the original location and restore the item in its
+        /// place. Directories are replaced and not merged. What was there is only removed once the
+        /// item was restored, so it's kept if restoring the item fails.
+        Overwrite,
+
+        /// Restore the item next to the existing one under a new name. For example
+        /// `report.txt` is restored as `report (restored).txt`, or as `report (restored 2).txt`
+        /// if that's taken as well.
+        Rename,
+
+        /// Leave the item in the trash and carry on with the next item.
+        Skip,
+    }
+    impl RestoreCollisionPolicy {
+        /// Returns `RestoreCollisionPolicy::Fail`
+        pub const fn new() -> Self {
+            RestoreCollisionPolicy::Fail
+        }
+    }
+    impl Default for RestoreCollisionPolicy {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    /// Preferences for [`restore_all_with_options`].
+    #[derive(Clone, Debug, Default)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct RestoreOptions {
+        pub collision_policy: RestoreCollisionPolicy,
+    }
+    impl RestoreOptions {
+        pub const fn new() -> Self {
+            RestoreOptions { collision_policy: RestoreCollisionPolicy::new() }
+        }
+    }
+
+    /// What happened to a single item during [`restore_all_with_options`].
+    #[derive(Debug)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub enum RestoreOutcome {
+        /// The item was restored to `path`. This is the original location of the item unless the
+        /// item was renamed due to [`RestoreCollisionPolicy::Rename`].
+        Restored { path: PathBuf },
+
+        /// `path` was taken and the policy was [`RestoreCollisionPolicy::Fail`]. The item is
+        /// still in the trash.
+        Collision { path: PathBuf },
+
+        /// `path` was taken and the policy was [`RestoreCollisionPolicy::Skip`]. The item is
+        /// still in the trash.
+        Skipped { path: PathBuf },
+
+        /// Restoring the item failed. The item is still in the trash.
+        Failed(Error),
+
+        /// The item was not attempted to be restored because a previous item failed or collided
+        /// with the [`RestoreCollisionPolicy::Fail`] policy, or the operation was cancelled. The
+        /// item is still in the trash.
+        NotAttempted,
+    }
+
+    /// Returns the path that [`RestoreCollisionPolicy::Rename`] tries after `attempt` collisions
+    /// at `path`. The suffix goes before the extension of files and at the end of the name of
+    /// directories.
+    pub(crate) fn renamed_restore_path(path: &Path, is_dir: bool, attempt: u32) -> PathBuf {
+        let suffix = match attempt {
+            1 => " (restored)".to_string(),
+            n => format!(" (restored {})", n),
+        };
+        let (stem, extension) = match (is_dir, path.file_stem(), path.extension()) {
+            (false, Some(stem), Some(extension)) => (stem, Some(extension)),
+            _ => (path.file_name().unwrap_or_default(), None),
+        };
+        let mut name = stem.to_owned();
+        name.push(suffix);
+        if let Some(extension) = extension {
+            name.push(".");
+            name.push(extension);
+        }
+        path.with_file_name(name)
+    }
+
+    /// Returns an unused path next to `target` that [`RestoreCollisionPolicy::Overwrite`] moves
+    /// what's at `target` to, until the item was restored over it.
+    pub(crate) fn overwritten_path(target: &Path) -> PathBuf {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        loop {
+            let mut name = OsString::from(".");
+            name.push(target.file_name().unwrap_or_default());
+            name.push(format!(
+                ".overwritten-{}-{}",
+                std::process::id(),
+                COUNTER.fetch_add(1, Ordering::Relaxed)
+            ));
+            let path = target.with_file_name(name);
+            if path.symlink_metadata().is_err() {
+                return path;
+            }
+        }
     }
 
     /// Returns all [`TrashItem`]s that are currently in the trash.
@@ -317,7 +1233,47 @@ pub mod os_limited {
     /// println!("{:#?}", trash_items);
     /// ```
     pub fn list() -> Result<Vec<TrashItem>, Error> {
-        platform::list()
+        DEFAULT_TRASH_CTX.list()
+    }
+
+    /// Returns an iterator over the [`TrashItem`]s that are currently in the trash.
+    ///
+    /// Unlike [`list`], this reads the items as the iterator advances, so the memory usage doesn't
+    /// grow with the number of items and iterating can be stopped early. An item that can't be
+    /// read, for example because its info file is malformed, is yielded as an error and the
+    /// iteration continues with the next item.
+    ///
+    /// On Windows the items are read up front.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use trash::os_limited::list_iter;
+    /// let first_page: Vec<_> = list_iter().unwrap().filter_map(Result::ok).take(20).collect();
+    /// println!("{:#?}", first_page);
+    /// ```
+    pub fn list_iter() -> Result<ListIter, Error> {
+        DEFAULT_TRASH_CTX.list_iter()
+    }
+
+    /// Returns the [`TrashItem`]s that match `query`, sorted and paginated as the query
+    /// specifies.
+    ///
+    /// See [`TrashQuery`] for an example.
+    pub fn query(query: &TrashQuery) -> Result<Vec<TrashItem>, Error> {
+        DEFAULT_TRASH_CTX.query(query)
+    }
+
+    /// An iterator over the items in the trash. See [`list_iter`].
+    pub struct ListIter {
+        inner: platform::ListIter,
+    }
+    impl Iterator for ListIter {
+        type Item = Result<TrashItem, Error>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            self.inner.next()
+        }
     }
 
     /// Deletes all the provided [`TrashItem`]s permanently.
@@ -342,7 +1298,7 @@ pub mod os_limited {
     where
         I: IntoIterator<Item = TrashItem>,
     {
-        platform::purge_all(items)
+        DEFAULT_TRASH_CTX.purge_all(items)
     }
 
     /// Restores all the provided [`TrashItem`] to their original location.
@@ -377,26 +1333,180 @@ pub mod os_limited {
     where
         I: IntoIterator<Item = TrashItem>,
     {
-        // Check for twins here cause that's pretty platform independent.
-        struct ItemWrapper<'a>(&'a TrashItem);
-        impl<'a> PartialEq for ItemWrapper<'a> {
-            fn eq(&self, other: &Self) -> bool {
-                self.0.original_path() == other.0.original_path()
-            }
-        }
-        impl<'a> Eq for ItemWrapper<'a> {}
-        impl<'a> Hash for ItemWrapper<'a> {
-            fn hash<H: Hasher>(&self, state: &mut H) {
-                self.0.original_path().hash(state);
-            }
-        }
-        let items = items.into_iter().collect::<Vec<_>>();
-        let mut item_set = HashSet::with_capacity(items.len());
-        for item in items.iter() {
-            if !item_set.insert(ItemWrapper(item)) {
-                return Err(Error::RestoreTwins { path: item.original_path(), items });
-            }
-        }
-        platform::restore_all(items)
+        DEFAULT_TRASH_CTX.restore_all(items)
+    }
+
+    /// Restores all the provided [`TrashItem`]s to their original location and reports the
+    /// outcome for each of them, in the order they were provided.
+    ///
+    /// Collisions are handled according to `options.collision_policy`. Unlike with
+    /// [`restore_all`], items with identical `original_path`s are allowed, the collision policy
+    /// applies to them as well.
+    ///
+    /// An error is only returned when the trash can't be accessed at all, or when the operation
+    /// was cancelled before the first item; the errors of single items are reported as
+    /// [`RestoreOutcome::Failed`]. With [`RestoreCollisionPolicy::Fail`] the items after the first
+    /// collision or failure are reported as [`RestoreOutcome::NotAttempted`], while the other
+    /// policies carry on with the next item.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::fs::File;
+    /// use trash::{
+    ///     delete,
+    ///     os_limited::{list, restore_all_with_options, RestoreCollisionPolicy, RestoreOptions, RestoreOutcome},
+    /// };
+    /// let filename = "trash-restore_all_with_options-example";
+    /// File::create(filename).unwrap();
+    /// delete(filename).unwrap();
+    /// File::create(filename).unwrap();
+    /// let options = RestoreOptions { collision_policy: RestoreCollisionPolicy::Rename };
+    /// let items = list().unwrap().into_iter().filter(|x| x.name == filename);
+    /// for (_item, outcome) in restore_all_with_options(items, &options).unwrap() {
+    ///     match outcome {
+    ///         RestoreOutcome::Restored { path } => std::fs::remove_file(path).unwrap(),
+    ///         _ => panic!("Unexpected outcome: {:?}", outcome),
+    ///     }
+    /// }
+    /// std::fs::remove_file(filename).unwrap();
+    /// ```
+    pub fn restore_all_with_options<I>(
+        items: I,
+        options: &RestoreOptions,
+    ) -> Result<Vec<(TrashItem, RestoreOutcome)>, Error>
+    where
+        I: IntoIterator<Item = TrashItem>,
+    {
+        DEFAULT_TRASH_CTX.restore_all_with_options(items, options)
+    }
+
+    /// Restores `item` into the `destination` directory instead of its original location.
+    ///
+    /// The item keeps its name unless `new_name` is provided. `destination` is created if it
+    /// doesn't exist. When the destination is on a different device than the trash, the item is
+    /// copied and then removed from the trash.
+    ///
+    /// Returns the path of the restored item. If this path is already taken, a
+    /// [`Error::RestoreCollision`] is returned with the item in `remaining_items`. A `new_name`
+    /// that isn't a single file name, like `../x` or `a/b`, is rejected with
+    /// [`Error::InvalidName`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::{ffi::OsStr, fs::File};
+    /// use trash::{delete, os_limited::{list, restore_to}};
+    /// let filename = "trash-restore_to-example";
+    /// File::create(filename).unwrap();
+    /// delete(filename).unwrap();
+    /// let item = list().unwrap().into_iter().find(|x| x.name == filename).unwrap();
+    /// let destination = std::env::temp_dir().join("trash-restore_to-example-destination");
+    /// let path = restore_to(item, &destination, Some(OsStr::new("restored"))).unwrap();
+    /// assert_eq!(path, destination.join("restored"));
+    /// std::fs::remove_dir_all(destination).unwrap();
+    /// ```
+    pub fn restore_to<P: AsRef<Path>>(
+        item: TrashItem,
+        destination: P,
+        new_name: Option<&OsStr>,
+    ) -> Result<PathBuf, Error> {
+        DEFAULT_TRASH_CTX.restore_to(item, destination, new_name)
+    }
+
+    /// Removes the items from the trash one by one and reports the outcome for each of them, in
+    /// the order they were provided. On Freedesktop, an item whose file is already gone from the
+    /// trash only has its info file removed and counts as succeeded.
+    ///
+    /// With [`BatchMode::StopOnError`] the remaining items are put into `not_attempted` after the
+    /// first failure, while [`BatchMode::ContinueOnError`] attempts all of them.
+    ///
+    /// See: [`purge_all`]
+    pub fn purge_all_with_report<I>(items: I, mode: BatchMode) -> BatchReport<TrashItem>
+    where
+        I: IntoIterator<Item = TrashItem>,
+    {
+        DEFAULT_TRASH_CTX.purge_all_with_report(items, mode)
+    }
+
+    /// Restores the items to their original location one by one and reports the outcome for each
+    /// of them, in the order they were provided. An item that collides with an existing file
+    /// fails with [`Error::RestoreCollision`], which has no `remaining_items` in this case.
+    ///
+    /// With [`BatchMode::StopOnError`] the remaining items are put into `not_attempted` after the
+    /// first failure, while [`BatchMode::ContinueOnError`] attempts all of them.
+    ///
+    /// See: [`restore_all`]
+    pub fn restore_all_with_report<I>(items: I, mode: BatchMode) -> BatchReport<TrashItem>
+    where
+        I: IntoIterator<Item = TrashItem>,
+    {
+        DEFAULT_TRASH_CTX.restore_all_with_report(items, mode)
+    }
+
+    /// Permanently removes every item of the trash that was deleted more than `max_age` ago, like
+    /// the "automatically empty the trash" setting of desktop environments.
+    ///
+    /// Items are selected by [`TrashItem::time_deleted`] across all trash folders. When `dry_run`
+    /// is true nothing is removed and the returned report lists the items that would be removed
+    /// and the number of bytes that would be freed. Otherwise an item that can't be removed is
+    /// reported in [`ExpiryReport::failed`] and the remaining items are still removed. Like with
+    /// [`enforce_quota`], an item whose size can't be determined is reported as failed and kept.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use trash::os_limited::expire;
+    /// let report = expire(Duration::from_secs(30 * 24 * 60 * 60), true).unwrap();
+    /// println!("Emptying the trash would free {} bytes", report.bytes);
+    /// ```
+    pub fn expire(max_age: Duration, dry_run: bool) -> Result<ExpiryReport, Error> {
+        DEFAULT_TRASH_CTX.expire(max_age, dry_run)
+    }
+
+    /// Permanently removes the least recently deleted items until the items left in the trash
+    /// occupy at most `max_bytes`, and returns the items that were evicted.
+    ///
+    /// The sizes are the ones reported by [`TrashItem::metadata`], which uses the
+    /// `directorysizes` cache on Freedesktop systems when it's up to date. The order of eviction
+    /// follows [`TrashItem::time_deleted`]. An item that can't be removed is reported in
+    /// [`QuotaReport::failed`] and the eviction continues with the next one. The items whose size
+    /// can't be determined are reported there as well, and are left in the trash.
+    ///
+    /// The quota applies to all the trash folders together. To limit the size of a single trash
+    /// folder on Freedesktop systems, see
+    /// [`crate::freedesktop::TrashContextExtFreedesktop::enforce_folder_quota`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use trash::os_limited::enforce_quota;
+    /// let report = enforce_quota(u64::MAX).unwrap();
+    /// assert!(report.evicted.is_empty());
+    /// ```
+    pub fn enforce_quota(max_bytes: u64) -> Result<QuotaReport, Error> {
+        DEFAULT_TRASH_CTX.enforce_quota(max_bytes)
+    }
+
+    /// Permanently removes everything from the trash.
+    ///
+    /// On Freedesktop systems this also removes what [`purge_all`] can't reach: files in the
+    /// `files` folder of a trash folder that have no info file, for example because the process
+    /// that was trashing them crashed, and info files whose file is gone. The `directorysizes`
+    /// caches and what [`crate::freedesktop::TrashContextExtFreedesktop::fsck`] moved into the
+    /// `quarantine` folders are removed as well. The trash folders themselves are kept.
+    ///
+    /// To empty a single trash folder on Freedesktop systems, see
+    /// [`crate::freedesktop::TrashContextExtFreedesktop::empty_folder`].
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use trash::os_limited::empty;
+    /// empty().unwrap();
+    /// ```
+    pub fn empty() -> Result<(), Error> {
+        DEFAULT_TRASH_CTX.empty()
     }
 }
@@ -0,0 +1,101 @@
+//! Asynchronous counterparts of the trash operations for use with the Tokio runtime.
+//!
+//! This module is only available when the `tokio` feature is enabled.
+//!
+//! The operations run on Tokio's blocking thread pool, so they don't stall the executor while
+//! they wait for the file system. Each function takes the [`TrashContext`] to use, pass
+//! `&trash::DEFAULT_TRASH_CTX` for the default preferences.
+//!
+//! # Cancellation
+//!
+//! The futures returned by this module are cancel-safe in the sense that dropping them never
+//! interrupts the file system operation midway. Once the future is polled for the first time, the
+//! operation runs to completion in the background even if the future is dropped, and only its
+//! result is discarded. This means that an item is never left half-trashed, for example with an
+//! info file but without the item itself, because its task was cancelled.
+
+use std::path::{Path, PathBuf};
+
+use crate::{Error, TrashContext};
+
+/// Runs `op` with a clone of `ctx` on the blocking thread pool and waits for it to finish.
+async fn run_blocking<F, R>(ctx: &TrashContext, op: F) -> Result<R, Error>
+where
+    F: FnOnce(&TrashContext) -> Result<R, Error> + Send + 'static,
+    R: Send + 'static,
+{
+    let ctx = ctx.clone();
+    match tokio::task::spawn_blocking(move || op(&ctx)).await {
+        Ok(result) => result,
+        Err(error) if error.is_panic() => std::panic::resume_unwind(error.into_panic()),
+        Err(error) => Err(Error::Unknown {
+            description: format!("The blocking task of a trash operation failed: {}", error),
+        }),
+    }
+}
+
+/// Asynchronous version of [`TrashContext::delete`].
+pub async fn delete<T: AsRef<Path>>(ctx: &TrashContext, path: T) -> Result<(), Error> {
+    let path = path.as_ref().to_owned();
+    run_blocking(ctx, move |ctx| ctx.delete(path)).await
+}
+
+/// Asynchronous version of [`TrashContext::delete_all`].
+///
+/// # Example
+///
+/// ```
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// use std::fs::File;
+/// use trash::{nonblocking, DEFAULT_TRASH_CTX};
+/// File::create("nonblocking-delete_all-example").unwrap();
+/// nonblocking::delete_all(&DEFAULT_TRASH_CTX, ["nonblocking-delete_all-example"]).await.unwrap();
+/// # }
+/// ```
+pub async fn delete_all<I, T>(ctx: &TrashContext, paths: I) -> Result<(), Error>
+where
+    I: IntoIterator<Item = T>,
+    T: AsRef<Path>,
+{
+    let paths: Vec<PathBuf> = paths.into_iter().map(|path| path.as_ref().to_owned()).collect();
+    run_blocking(ctx, move |ctx| ctx.delete_all(paths)).await
+}
+
+#[cfg(any(
+    target_os = "windows",
+    all(unix, not(target_os = "macos"), not(target_os = "ios"), not(target_os = "android"))
+))]
+pub use os_limited::*;
+
+#[cfg(any(
+    target_os = "windows",
+    all(unix, not(target_os = "macos"), not(target_os = "ios"), not(target_os = "android"))
+))]
+mod os_limited {
+    use super::run_blocking;
+    use crate::{os_limited::TrashContextExtOsLimited, Error, TrashContext, TrashItem};
+
+    /// Asynchronous version of [`crate::os_limited::list`].
+    pub async fn list(ctx: &TrashContext) -> Result<Vec<TrashItem>, Error> {
+        run_blocking(ctx, |ctx| ctx.list()).await
+    }
+
+    /// Asynchronous version of [`crate::os_limited::purge_all`].
+    pub async fn purge_all<I>(ctx: &TrashContext, items: I) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = TrashItem>,
+    {
+        let items: Vec<_> = items.into_iter().collect();
+        run_blocking(ctx, move |ctx| ctx.purge_all(items)).await
+    }
+
+    /// Asynchronous version of [`crate::os_limited::restore_all`].
+    pub async fn restore_all<I>(ctx: &TrashContext, items: I) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = TrashItem>,
+    {
+        let items: Vec<_> = items.into_iter().collect();
+        run_blocking(ctx, move |ctx| ctx.restore_all(items)).await
+    }
+}
@@ -0,0 +1,381 @@
+use std::{cmp::Ordering, path::PathBuf};
+
+use log::warn;
+
+use crate::{Error, TrashContext, TrashItem, TrashItemKind};
+
+use super::TrashContextExtOsLimited;
+
+/// The field that the results of a [`TrashQuery`] are sorted by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortBy {
+    /// Keep the order the items are listed in, which is not specified. This allows the listing to
+    /// stop as soon as enough items were found.
+    ///
+    /// This is the default.
+    Unsorted,
+    Name,
+    OriginalPath,
+    TimeDeleted,
+}
+impl SortBy {
+    /// Returns `SortBy::Unsorted`
+    pub const fn new() -> Self {
+        SortBy::Unsorted
+    }
+}
+impl Default for SortBy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The direction that the results of a [`TrashQuery`] are sorted in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortOrder {
+    Ascending,
+    Descending,
+}
+
+/// Selects items from the trash by their original location, name, deletion time, and kind.
+///
+/// All of the criteria have to match for an item to be selected. Items that can't be read are
+/// skipped.
+///
+/// # Example
+///
+/// ```
+/// use std::time::{SystemTime, UNIX_EPOCH};
+/// use trash::os_limited::{query, SortBy, SortOrder, TrashQuery};
+/// let an_hour_ago = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64 - 3600;
+/// let recent_logs = TrashQuery::new()
+///     .name_glob("*.log")
+///     .deleted_after(an_hour_ago)
+///     .sort_by(SortBy::TimeDeleted, SortOrder::Descending)
+///     .limit(10);
+/// for item in query(&recent_logs).unwrap() {
+///     println!("{:?}", item.original_path());
+/// }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct TrashQuery {
+    parent_prefix: Option<PathBuf>,
+    name_glob: Option<String>,
+    deleted_after: Option<i64>,
+    deleted_before: Option<i64>,
+    kinds: Vec<TrashItemKind>,
+    sort_by: SortBy,
+    order: Option<SortOrder>,
+    offset: usize,
+    limit: Option<usize>,
+}
+impl TrashQuery {
+    /// Returns a query that selects every item.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Selects the items that were deleted from `prefix` or from any directory under it.
+    pub fn original_parent_prefix(mut self, prefix: impl Into<PathBuf>) -> Self {
+        self.parent_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Selects the items whose name matches `pattern`.
+    ///
+    /// `*` matches any sequence of characters, `?` matches a single character, and `[...]`
+    /// matches one of the enclosed characters or ranges like `[a-z]`. The set is negated when it
+    /// starts with `!` or `^`. A backslash matches the next character literally. Names that are
+    /// not valid Unicode are matched by their `name_lossy`.
+    pub fn name_glob(mut self, pattern: impl Into<String>) -> Self {
+        self.name_glob = Some(pattern.into());
+        self
+    }
+
+    /// Selects the items that were deleted at or after `time`, in seconds since the UNIX Epoch.
+    pub fn deleted_after(mut self, time: i64) -> Self {
+        self.deleted_after = Some(time);
+        self
+    }
+
+    /// Selects the items that were deleted before `time`, in seconds since the UNIX Epoch.
+    pub fn deleted_before(mut self, time: i64) -> Self {
+        self.deleted_before = Some(time);
+        self
+    }
+
+    /// Selects the items of the given kind. Calling this multiple times selects the items of any
+    /// of the given kinds.
+    ///
+    /// Note that this is the most expensive criterion, because it requires reading the metadata
+    /// of the items. It's only checked for the items that match all the other criteria.
+    pub fn kind(mut self, kind: TrashItemKind) -> Self {
+        if !self.kinds.contains(&kind) {
+            self.kinds.push(kind);
+        }
+        self
+    }
+
+    /// Sorts the selected items by `sort_by` in the given `order`.
+    pub fn sort_by(mut self, sort_by: SortBy, order: SortOrder) -> Self {
+        self.sort_by = sort_by;
+        self.order = Some(order);
+        self
+    }
+
+    /// Skips the first `offset` selected items, after sorting.
+    pub fn offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Returns at most `limit` items, after sorting and skipping the `offset`.
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Returns true if `item` matches all the criteria of this query.
+    ///
+    /// Returns false if the kind of the item is required but its metadata can't be read.
+    pub fn matches(&self, item: &TrashItem) -> bool {
+        self.matches_cheaply(item) && self.matches_kind(item)
+    }
+
+    fn matches_cheaply(&self, item: &TrashItem) -> bool {
+        if let Some(prefix) = &self.parent_prefix {
+            if !item.original_parent.starts_with(prefix) {
+                return false;
+            }
+        }
+        if let Some(pattern) = &self.name_glob {
+            if !glob_matches(pattern, &item.name_lossy()) {
+                return false;
+            }
+        }
+        if self.deleted_after.is_some_and(|time| item.time_deleted < time) {
+            return false;
+        }
+        if self.deleted_before.is_some_and(|time| item.time_deleted >= time) {
+            return false;
+        }
+        true
+    }
+
+    fn matches_kind(&self, item: &TrashItem) -> bool {
+        if self.kinds.is_empty() {
+            return true;
+        }
+        match item.metadata() {
+            Ok(metadata) => self.kinds.contains(&metadata.kind),
+            Err(error) => {
+                warn!("Could not read the metadata of {:?}. The error was: {}", item.id, error);
+                false
+            }
+        }
+    }
+
+    /// Runs the query against the trash of `ctx`.
+    pub(crate) fn run(&self, ctx: &TrashContext) -> Result<Vec<TrashItem>, Error> {
+        let mut selected = Vec::new();
+        // Without sorting, the listing can stop as soon as the requested page is complete.
+        let needed = match (self.sort_by, self.limit) {
+            (SortBy::Unsorted, Some(limit)) => Some(self.offset.saturating_add(limit)),
+            _ => None,
+        };
+        for item in ctx.list_iter()? {
+            if needed.is_some_and(|needed| selected.len() >= needed) {
+                break;
+            }
+            let item = match item {
+                Ok(item) => item,
+                Err(error) => {
+                    warn!("Skipping an item of the trash. The error was: {}", error);
+                    continue;
+                }
+            };
+            if self.matches(&item) {
+                selected.push(item);
+            }
+        }
+        Ok(self.sort_and_page(selected))
+    }
+
+    fn sort_and_page(&self, mut selected: Vec<TrashItem>) -> Vec<TrashItem> {
+        if let Some(order) = self.order {
+            selected.sort_by(|a, b| {
+                let ordering = compare(self.sort_by, a, b);
+                match order {
+                    SortOrder::Ascending => ordering,
+                    SortOrder::Descending => ordering.reverse(),
+                }
+            });
+        }
+        let page = selected.into_iter().skip(self.offset);
+        match self.limit {
+            Some(limit) => page.take(limit).collect(),
+            None => page.collect(),
+        }
+    }
+}
+
+fn compare(sort_by: SortBy, a: &TrashItem, b: &TrashItem) -> Ordering {
+    match sort_by {
+        SortBy::Unsorted => Ordering::Equal,
+        SortBy::Name => a.name.cmp(&b.name),
+        SortBy::OriginalPath => a.original_path().cmp(&b.original_path()),
+        SortBy::TimeDeleted => a.time_deleted.cmp(&b.time_deleted),
+    }
+}
+
+/// Matches `text` against a glob `pattern`. See [`TrashQuery::name_glob`].
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // The position of the last `*` in the pattern and the text position it was tried at.
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        let step = match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+                continue;
+            }
+            Some('?') => Some(1),
+            Some('[') => match_set(&pattern[p..], text[t]),
+            Some('\\') if p + 1 < pattern.len() => (pattern[p + 1] == text[t]).then_some(2),
+            Some(&c) => (c == text[t]).then_some(1),
+            None => None,
+        };
+        match (step, backtrack) {
+            (Some(step), _) => {
+                p += step;
+                t += 1;
+            }
+            (None, Some((star, star_t))) => {
+                // Let the last `*` consume one more character.
+                backtrack = Some((star, star_t + 1));
+                p = star + 1;
+                t = star_t + 1;
+            }
+            (None, None) => return false,
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Matches `c` against the set at the start of `pattern`. Returns the length of the set if it
+/// matches. A `[` without a closing `]` matches itself.
+fn match_set(pattern: &[char], c: char) -> Option<usize> {
+    let mut i = 1;
+    let negated = matches!(pattern.get(i), Some('!') | Some('^'));
+    if negated {
+        i += 1;
+    }
+    let mut matched = false;
+    let mut first = true;
+    while i < pattern.len() {
+        if pattern[i] == ']' && !first {
+            return (matched != negated).then_some(i + 1);
+        }
+        first = false;
+        let low = pattern[i];
+        if i + 2 < pattern.len() && pattern[i + 1] == '-' && pattern[i + 2] != ']' {
+            matched |= low <= c && c <= pattern[i + 2];
+            i += 3;
+        } else {
+            matched |= low == c;
+            i += 1;
+        }
+    }
+    (c == '[').then_some(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{glob_matches, SortBy, SortOrder, TrashQuery};
+    use crate::TrashItem;
+
+    fn item(parent: &str, name: &str, time_deleted: i64) -> TrashItem {
+        TrashItem {
+            id: format!("{}/{}", parent, name).into(),
+            name: name.into(),
+            original_parent: PathBuf::from(parent),
+            time_deleted,
+        }
+    }
+
+    #[test]
+    fn test_glob() {
+        let cases = [
+            ("*.log", "app.log", true),
+            ("*.log", "app.log.1", false),
+            ("*.log*", "app.log.1", true),
+            ("a?c", "abc", true),
+            ("a?c", "ac", false),
+            ("*", "", true),
+            ("", "", true),
+            ("", "a", false),
+            ("*a*b*", "xxaxxbxx", true),
+            ("*a*b", "xxaxxbxxc", false),
+            ("[abc]x", "bx", true),
+            ("[a-c]x", "dx", false),
+            ("[!a-c]x", "dx", true),
+            ("[^a-c]x", "ax", false),
+            ("[]]", "]", true),
+            ("[a-]", "-", true),
+            ("[", "[", true),
+            ("\\*", "*", true),
+            ("\\*", "a", false),
+            ("é*", "été", true),
+        ];
+        for (pattern, text, expected) in cases {
+            assert_eq!(glob_matches(pattern, text), expected, "{:?} {:?}", pattern, text);
+        }
+    }
+
+    #[test]
+    fn test_matches() {
+        let query = TrashQuery::new()
+            .original_parent_prefix("/home/me/project")
+            .name_glob("*.log")
+            .deleted_after(100)
+            .deleted_before(200);
+        assert!(query.matches(&item("/home/me/project", "a.log", 100)));
+        assert!(query.matches(&item("/home/me/project/sub", "a.log", 199)));
+        assert!(!query.matches(&item("/home/me/project2", "a.log", 150)));
+        assert!(!query.matches(&item("/home/me/project", "a.txt", 150)));
+        assert!(!query.matches(&item("/home/me/project", "a.log", 99)));
+        assert!(!query.matches(&item("/home/me/project", "a.log", 200)));
+    }
+
+    #[test]
+    fn test_sort_and_page() {
+        let items = vec![item("/b", "2", 3), item("/a", "3", 1), item("/c", "1", 2)];
+        let names = |query: TrashQuery| {
+            let selected = query.sort_and_page(items.clone());
+            selected.into_iter().map(|x| x.name.into_string().unwrap()).collect::<Vec<_>>()
+        };
+        let query = TrashQuery::new();
+        assert_eq!(
+            names(query.clone().sort_by(SortBy::Name, SortOrder::Ascending)),
+            ["1", "2", "3"]
+        );
+        assert_eq!(
+            names(query.clone().sort_by(SortBy::OriginalPath, SortOrder::Ascending)),
+            ["3", "2", "1"]
+        );
+        assert_eq!(
+            names(query.clone().sort_by(SortBy::TimeDeleted, SortOrder::Descending)),
+            ["2", "1", "3"]
+        );
+        assert_eq!(
+            names(query.clone().sort_by(SortBy::Name, SortOrder::Ascending).offset(1).limit(1)),
+            ["2"]
+        );
+        assert_eq!(names(query.offset(5)), Vec::<String>::new());
+    }
+}
@@ -2,11 +2,10 @@ use std::fs::{create_dir, File};
 use std::path::PathBuf;
 use std::sync::atomic::{AtomicI64, Ordering};
 
-use chrono;
 use log::trace;
 use once_cell::sync::Lazy;
 
-use crate::{delete, delete_all};
+use crate::{delete, delete_all, delete_all_with_report, BatchMode};
 
 // WARNING Expecting that `cargo test` won't be invoked on the same computer more than once within
 // a single millisecond
@@ -45,11 +44,27 @@ fn test_delete_folder() {
 
     assert!(path.exists());
     delete(&path).unwrap();
-    assert!(path.exists() == false);
+    assert!(!path.exists());
 
     trace!("Finished test_delete_folder");
 }
 
+#[test]
+fn test_delete_nested_folder() {
+    init_logging();
+    trace!("Started test_delete_nested_folder");
+
+    let path = PathBuf::from(get_unique_name());
+    create_dir(&path).unwrap();
+    create_dir(path.join("folder_in_folder")).unwrap();
+    File::create(path.join("folder_in_folder").join("file_in_folder")).unwrap();
+
+    delete(&path).unwrap();
+    assert!(!path.exists());
+
+    trace!("Finished test_delete_nested_folder");
+}
+
 #[test]
 fn test_delete_all() {
     init_logging();
@@ -68,6 +83,31 @@ fn test_delete_all() {
     trace!("Finished test_delete_all");
 }
 
+#[test]
+fn test_delete_all_with_report() {
+    init_logging();
+    trace!("Started test_delete_all_with_report");
+    let prefix = get_unique_name();
+    let paths: Vec<_> = (0..3).map(|i| format!("{}#{}", prefix, i)).collect();
+    File::create(&paths[0]).unwrap();
+    File::create(&paths[2]).unwrap();
+
+    let report = delete_all_with_report(&paths, BatchMode::StopOnError);
+    assert!(!report.is_success());
+    assert_eq!(report.succeeded, vec![PathBuf::from(&paths[0])]);
+    assert_eq!(report.failed.len(), 1);
+    assert_eq!(report.failed[0].0, PathBuf::from(&paths[1]));
+    assert_eq!(report.not_attempted, vec![PathBuf::from(&paths[2])]);
+    assert!(File::open(&paths[2]).is_ok());
+
+    let report = delete_all_with_report(&paths[1..], BatchMode::ContinueOnError);
+    assert_eq!(report.succeeded, vec![PathBuf::from(&paths[2])]);
+    assert_eq!(report.failed.len(), 1);
+    assert!(report.not_attempted.is_empty());
+    assert!(File::open(&paths[2]).is_err());
+    trace!("Finished test_delete_all_with_report");
+}
+
 #[cfg(unix)]
 mod unix {
     use log::trace;
@@ -127,6 +167,7 @@ mod unix {
 ))]
 mod os_limited {
     use std::collections::{hash_map::Entry, HashMap};
+    use std::path::Path;
 
     use super::*;
 
@@ -138,8 +179,8 @@ mod os_limited {
         init_logging();
 
         let deletion_time = chrono::Utc::now();
-        let actual_unix_deletion_time = deletion_time.naive_utc().timestamp();
-        assert_eq!(actual_unix_deletion_time, deletion_time.naive_local().timestamp());
+        let actual_unix_deletion_time = deletion_time.naive_utc().and_utc().timestamp();
+        assert_eq!(actual_unix_deletion_time, deletion_time.naive_local().and_utc().timestamp());
         let file_name_prefix = get_unique_name();
         let batches: usize = 2;
         let files_per_batch: usize = 3;
@@ -154,9 +195,9 @@ mod os_limited {
         let items = trash::os_limited::list().unwrap();
         let items: HashMap<_, Vec<_>> = items
             .into_iter()
-            .filter(|x| x.name.starts_with(&file_name_prefix))
+            .filter(|x| x.name_lossy().starts_with(&file_name_prefix))
             .fold(HashMap::new(), |mut map, x| {
-                match map.entry(x.name.clone()) {
+                match map.entry(x.name_lossy().into_owned()) {
                     Entry::Occupied(mut entry) => {
                         entry.get_mut().push(x);
                     }
@@ -187,7 +228,68 @@ mod os_limited {
 
         // Let's try to purge all the items we just created but ignore any errors
         // as this test should succeed as long as `list` works properly.
-        let _ = trash::os_limited::purge_all(items.into_iter().map(|(_name, item)| item).flatten());
+        let _ = trash::os_limited::purge_all(items.into_values().flatten());
+    }
+
+    #[test]
+    fn list_iter() {
+        init_logging();
+        let file_name_prefix = get_unique_name();
+        let names: Vec<_> = (0..3).map(|i| format!("{}#{}", file_name_prefix, i)).collect();
+        for path in names.iter() {
+            File::create(path).unwrap();
+        }
+        trash::delete_all(&names).unwrap();
+
+        let mut iter = trash::os_limited::list_iter().unwrap();
+        let first = iter
+            .by_ref()
+            .filter_map(Result::ok)
+            .find(|x| x.name_lossy().starts_with(&file_name_prefix))
+            .unwrap();
+        let rest: Vec<_> = iter
+            .filter_map(Result::ok)
+            .filter(|x| x.name_lossy().starts_with(&file_name_prefix))
+            .collect();
+        assert_eq!(rest.len(), names.len() - 1);
+        assert!(rest.iter().all(|x| x.id != first.id));
+        trash::os_limited::purge_all(std::iter::once(first).chain(rest)).unwrap();
+    }
+
+    #[test]
+    fn query() {
+        use trash::os_limited::{SortBy, SortOrder, TrashQuery};
+        init_logging();
+        let file_name_prefix = get_unique_name();
+        let names: Vec<_> = ["b.log", "a.log", "c.txt"]
+            .iter()
+            .map(|x| format!("{}#{}", file_name_prefix, x))
+            .collect();
+        for path in names.iter() {
+            File::create(path).unwrap();
+        }
+        trash::delete_all(&names).unwrap();
+
+        let parent = std::env::current_dir().unwrap().canonicalize().unwrap();
+        let query = TrashQuery::new()
+            .original_parent_prefix(parent)
+            .name_glob(format!("{}#*.log", file_name_prefix))
+            .kind(trash::TrashItemKind::File)
+            .sort_by(SortBy::Name, SortOrder::Ascending);
+        let found = trash::os_limited::query(&query).unwrap();
+        let found_names: Vec<_> = found.iter().map(|x| x.name_lossy().into_owned()).collect();
+        assert_eq!(found_names, [names[1].clone(), names[0].clone()]);
+
+        let first_page = trash::os_limited::query(&query.clone().limit(1)).unwrap();
+        assert_eq!(first_page.len(), 1);
+        assert_eq!(first_page[0].name, names[1].as_str());
+
+        let all = trash::os_limited::query(
+            &TrashQuery::new().name_glob(format!("{}#*", file_name_prefix)),
+        )
+        .unwrap();
+        assert_eq!(all.len(), names.len());
+        trash::os_limited::purge_all(all).unwrap();
     }
 
     #[test]
@@ -202,6 +304,133 @@ mod os_limited {
         trash::os_limited::restore_all(vec![]).unwrap();
     }
 
+    #[test]
+    fn item_from_id() {
+        init_logging();
+        let name = get_unique_name();
+        File::create(&name).unwrap();
+        trash::delete(&name).unwrap();
+        let item =
+            trash::os_limited::list().unwrap().into_iter().find(|x| x.name == *name).unwrap();
+
+        let rebuilt = trash::TrashItem::from_id(item.id.clone()).unwrap();
+        assert_eq!(rebuilt.id, item.id);
+        assert_eq!(rebuilt.original_path(), item.original_path());
+        assert_eq!(rebuilt.time_deleted, item.time_deleted);
+        trash::os_limited::purge_all(vec![item.clone()]).unwrap();
+        assert!(matches!(
+            trash::TrashItem::from_id(item.id),
+            Err(trash::Error::CouldNotAccess { .. })
+        ));
+        assert!(trash::TrashItem::from_id(name).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let item = trash::TrashItem {
+            id: "id".into(),
+            name: "name".into(),
+            original_parent: "/parent".into(),
+            time_deleted: 42,
+        };
+        let json = serde_json::to_string(&item).unwrap();
+        let item: trash::TrashItem = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            (item.id.to_str(), item.original_parent.to_str()),
+            (Some("id"), Some("/parent"))
+        );
+        assert_eq!((item.name_lossy().as_ref(), item.time_deleted), ("name", 42));
+
+        for kind in [std::io::ErrorKind::NotFound, std::io::ErrorKind::InvalidData] {
+            let source = std::io::Error::new(kind, "message");
+            let error = trash::Error::FileSystem { path: "/parent".into(), source };
+            let error: trash::Error =
+                serde_json::from_str(&serde_json::to_string(&error).unwrap()).unwrap();
+            match error {
+                trash::Error::FileSystem { path, source } => {
+                    assert_eq!(path, Path::new("/parent"));
+                    assert_eq!(source.kind(), kind);
+                    assert_eq!(source.to_string(), "message");
+                }
+                _ => panic!("Unexpected error: {:?}", error),
+            }
+        }
+        let old_format = r#"{"FileSystem":{"path":"/parent","source":{"raw_os_error":null,"message":"message"}}}"#;
+        let error: trash::Error = serde_json::from_str(old_format).unwrap();
+        assert!(
+            matches!(error, trash::Error::FileSystem { source, .. } if source.kind() == std::io::ErrorKind::Other)
+        );
+        let error = trash::Error::FileSystem {
+            path: "/parent".into(),
+            source: std::io::Error::from_raw_os_error(2),
+        };
+        let error: trash::Error =
+            serde_json::from_str(&serde_json::to_string(&error).unwrap()).unwrap();
+        assert!(
+            matches!(error, trash::Error::FileSystem { source, .. } if source.raw_os_error() == Some(2) && source.kind() == std::io::ErrorKind::NotFound)
+        );
+    }
+
+    #[cfg(all(feature = "serde", unix))]
+    #[test]
+    fn deserialized_bogus_id() {
+        init_logging();
+        for id in ["/", "foo", "", "/parent/files/name"] {
+            let item = trash::TrashItem {
+                id: id.into(),
+                name: "name".into(),
+                original_parent: "/parent".into(),
+                time_deleted: 42,
+            };
+            let json = serde_json::to_string(&item).unwrap();
+            let item: trash::TrashItem = serde_json::from_str(&json).unwrap();
+            assert!(matches!(item.metadata(), Err(trash::Error::CouldNotAccess { .. })));
+            assert!(matches!(
+                trash::os_limited::purge_all(vec![item.clone()]),
+                Err(trash::Error::CouldNotAccess { .. })
+            ));
+            assert!(matches!(
+                trash::os_limited::restore_all(vec![item]),
+                Err(trash::Error::CouldNotAccess { .. })
+            ));
+        }
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn nonblocking() {
+        use trash::{nonblocking, DEFAULT_TRASH_CTX};
+        init_logging();
+        let names: Vec<_> = (0..3).map(|_| get_unique_name()).collect();
+        for name in names.iter() {
+            File::create(name).unwrap();
+        }
+        nonblocking::delete_all(&DEFAULT_TRASH_CTX, &names[..2]).await.unwrap();
+        nonblocking::delete(&DEFAULT_TRASH_CTX, &names[2]).await.unwrap();
+        assert!(names.iter().all(|name| !Path::new(name).exists()));
+
+        let mut items: Vec<_> = nonblocking::list(&DEFAULT_TRASH_CTX)
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|item| names.iter().any(|name| item.name == **name))
+            .collect();
+        assert_eq!(items.len(), 3);
+        let restored = items.pop().unwrap();
+        nonblocking::restore_all(&DEFAULT_TRASH_CTX, vec![restored.clone()]).await.unwrap();
+        assert!(restored.original_path().exists());
+        nonblocking::purge_all(&DEFAULT_TRASH_CTX, items).await.unwrap();
+        let remaining = nonblocking::list(&DEFAULT_TRASH_CTX)
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|item| names.iter().any(|name| item.name == **name))
+            .count();
+        assert_eq!(remaining, 0);
+        std::fs::remove_file(restored.original_path()).unwrap();
+    }
+
     #[test]
     fn purge() {
         init_logging();
@@ -221,14 +450,14 @@ mod os_limited {
         let targets: Vec<_> = trash::os_limited::list()
             .unwrap()
             .into_iter()
-            .filter(|x| x.name.starts_with(&file_name_prefix))
+            .filter(|x| x.name_lossy().starts_with(&file_name_prefix))
             .collect();
         assert_eq!(targets.len(), batches * files_per_batch);
         trash::os_limited::purge_all(targets).unwrap();
         let remaining = trash::os_limited::list()
             .unwrap()
             .into_iter()
-            .filter(|x| x.name.starts_with(&file_name_prefix))
+            .filter(|x| x.name_lossy().starts_with(&file_name_prefix))
             .count();
         assert_eq!(remaining, 0);
     }
@@ -249,14 +478,14 @@ mod os_limited {
         let targets: Vec<_> = trash::os_limited::list()
             .unwrap()
             .into_iter()
-            .filter(|x| x.name.starts_with(&file_name_prefix))
+            .filter(|x| x.name_lossy().starts_with(&file_name_prefix))
             .collect();
         assert_eq!(targets.len(), file_count);
         trash::os_limited::restore_all(targets).unwrap();
         let remaining = trash::os_limited::list()
             .unwrap()
             .into_iter()
-            .filter(|x| x.name.starts_with(&file_name_prefix))
+            .filter(|x| x.name_lossy().starts_with(&file_name_prefix))
             .count();
         assert_eq!(remaining, 0);
 
@@ -289,16 +518,15 @@ mod os_limited {
         let mut targets: Vec<_> = trash::os_limited::list()
             .unwrap()
             .into_iter()
-            .filter(|x| x.name.starts_with(&file_name_prefix))
+            .filter(|x| x.name_lossy().starts_with(&file_name_prefix))
             .collect();
         targets.sort_by(|a, b| a.name.cmp(&b.name));
         assert_eq!(targets.len(), file_count);
-        let remaining_count;
-        match trash::os_limited::restore_all(targets) {
+        let remaining_count = match trash::os_limited::restore_all(targets) {
             Err(trash::Error::RestoreCollision { remaining_items, .. }) => {
                 let contains = |v: &Vec<trash::TrashItem>, name: &String| {
                     for curr in v.iter() {
-                        if *curr.name == *name {
+                        if curr.name == name.as_str() {
                             return true;
                         }
                     }
@@ -308,16 +536,16 @@ mod os_limited {
                 for path in names.iter().filter(|filename| !contains(&remaining_items, filename)) {
                     assert!(File::open(path).is_ok());
                 }
-                remaining_count = remaining_items.len();
-            },
+                remaining_items.len()
+            }
             _ => panic!(
                 "restore_all was expected to return `trash::ErrorKind::RestoreCollision` but did not."
             ),
-        }
+        };
         let remaining = trash::os_limited::list()
             .unwrap()
             .into_iter()
-            .filter(|x| x.name.starts_with(&file_name_prefix))
+            .filter(|x| x.name_lossy().starts_with(&file_name_prefix))
             .collect::<Vec<_>>();
         assert_eq!(remaining.len(), remaining_count);
         trash::os_limited::purge_all(remaining).unwrap();
@@ -327,6 +555,147 @@ mod os_limited {
         }
     }
 
+    #[test]
+    fn restore_with_collision_policies() {
+        use trash::os_limited::{RestoreCollisionPolicy, RestoreOptions, RestoreOutcome};
+        init_logging();
+        let policies = [
+            RestoreCollisionPolicy::Fail,
+            RestoreCollisionPolicy::Skip,
+            RestoreCollisionPolicy::Overwrite,
+            RestoreCollisionPolicy::Rename,
+        ];
+        for policy in policies {
+            let file_name_prefix = get_unique_name();
+            let names: Vec<_> = (0..2).map(|i| format!("{}#{}.txt", file_name_prefix, i)).collect();
+            for path in names.iter() {
+                std::fs::write(path, "trashed").unwrap();
+            }
+            trash::delete_all(&names).unwrap();
+            std::fs::write(&names[0], "existing").unwrap();
+
+            let mut targets: Vec<_> = trash::os_limited::list()
+                .unwrap()
+                .into_iter()
+                .filter(|x| x.name_lossy().starts_with(&file_name_prefix))
+                .collect();
+            targets.sort_by(|a, b| a.name.cmp(&b.name));
+            assert_eq!(targets.len(), names.len());
+            let options = RestoreOptions { collision_policy: policy };
+            let outcomes = trash::os_limited::restore_all_with_options(targets, &options).unwrap();
+            assert_eq!(outcomes.len(), names.len());
+            let renamed = PathBuf::from(format!("{}#0 (restored).txt", file_name_prefix));
+            match (policy, &outcomes[0].1, &outcomes[1].1) {
+                (
+                    RestoreCollisionPolicy::Fail,
+                    RestoreOutcome::Collision { .. },
+                    RestoreOutcome::NotAttempted,
+                ) => {
+                    assert_eq!(std::fs::read_to_string(&names[0]).unwrap(), "existing");
+                    assert!(!Path::new(&names[1]).exists());
+                }
+                (
+                    RestoreCollisionPolicy::Skip,
+                    RestoreOutcome::Skipped { .. },
+                    RestoreOutcome::Restored { .. },
+                ) => {
+                    assert_eq!(std::fs::read_to_string(&names[0]).unwrap(), "existing");
+                }
+                (
+                    RestoreCollisionPolicy::Overwrite,
+                    RestoreOutcome::Restored { path },
+                    RestoreOutcome::Restored { .. },
+                ) => {
+                    assert_eq!(path.file_name(), Path::new(&names[0]).file_name());
+                    assert_eq!(std::fs::read_to_string(&names[0]).unwrap(), "trashed");
+                }
+                (
+                    RestoreCollisionPolicy::Rename,
+                    RestoreOutcome::Restored { path },
+                    RestoreOutcome::Restored { .. },
+                ) => {
+                    assert_eq!(path.file_name(), renamed.file_name());
+                    assert_eq!(std::fs::read_to_string(&names[0]).unwrap(), "existing");
+                    assert_eq!(std::fs::read_to_string(&renamed).unwrap(), "trashed");
+                    std::fs::remove_file(&renamed).unwrap();
+                }
+                outcome => panic!("Unexpected outcome: {:?}", outcome),
+            }
+            if policy != RestoreCollisionPolicy::Fail {
+                assert_eq!(std::fs::read_to_string(&names[1]).unwrap(), "trashed");
+            }
+
+            let remaining: Vec<_> = trash::os_limited::list()
+                .unwrap()
+                .into_iter()
+                .filter(|x| x.name_lossy().starts_with(&file_name_prefix))
+                .collect();
+            let expected_remaining = match policy {
+                RestoreCollisionPolicy::Fail => 2,
+                RestoreCollisionPolicy::Skip => 1,
+                _ => 0,
+            };
+            assert_eq!(remaining.len(), expected_remaining);
+            trash::os_limited::purge_all(remaining).unwrap();
+            for path in names.iter() {
+                let _ = std::fs::remove_file(path);
+            }
+        }
+    }
+
+    #[test]
+    fn renamed_restore_path() {
+        use trash::os_limited::renamed_restore_path;
+        let path = Path::new("/tmp/report.txt");
+        assert_eq!(renamed_restore_path(path, false, 1), Path::new("/tmp/report (restored).txt"));
+        assert_eq!(renamed_restore_path(path, false, 2), Path::new("/tmp/report (restored 2).txt"));
+        let path = Path::new("/tmp/photos.2020");
+        assert_eq!(renamed_restore_path(path, true, 1), Path::new("/tmp/photos.2020 (restored)"));
+        let path = Path::new("/tmp/.bashrc");
+        assert_eq!(renamed_restore_path(path, false, 1), Path::new("/tmp/.bashrc (restored)"));
+    }
+
+    #[test]
+    fn purge_and_restore_with_report() {
+        use trash::{
+            os_limited::{purge_all_with_report, restore_all_with_report},
+            BatchMode,
+        };
+        init_logging();
+        let file_name_prefix = get_unique_name();
+        let names: Vec<_> = (0..3).map(|i| format!("{}#{}", file_name_prefix, i)).collect();
+        for path in names.iter() {
+            File::create(path).unwrap();
+        }
+        trash::delete_all(&names).unwrap();
+        File::create(&names[1]).unwrap();
+
+        let mut targets: Vec<_> = trash::os_limited::list()
+            .unwrap()
+            .into_iter()
+            .filter(|x| x.name_lossy().starts_with(&file_name_prefix))
+            .collect();
+        targets.sort_by(|a, b| a.name.cmp(&b.name));
+        let report = restore_all_with_report(targets, BatchMode::ContinueOnError);
+        assert_eq!(report.succeeded.len(), 2);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].0.name, names[1].as_str());
+        assert!(matches!(report.failed[0].1, trash::Error::RestoreCollision { .. }));
+
+        let failed: Vec<_> = report.failed.into_iter().map(|(item, _)| item).collect();
+        let report = purge_all_with_report(failed, BatchMode::StopOnError);
+        assert!(report.is_success());
+        let remaining = trash::os_limited::list()
+            .unwrap()
+            .into_iter()
+            .filter(|x| x.name_lossy().starts_with(&file_name_prefix))
+            .count();
+        assert_eq!(remaining, 0);
+        for path in names.iter() {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
     #[test]
     fn restore_twins() {
         init_logging();
@@ -341,12 +710,12 @@ mod os_limited {
 
         let twin_name = &names[1];
         File::create(twin_name).unwrap();
-        trash::delete(&twin_name).unwrap();
+        trash::delete(twin_name).unwrap();
 
         let mut targets: Vec<_> = trash::os_limited::list()
             .unwrap()
             .into_iter()
-            .filter(|x| x.name.starts_with(&file_name_prefix))
+            .filter(|x| x.name_lossy().starts_with(&file_name_prefix))
             .collect();
         targets.sort_by(|a, b| a.name.cmp(&b.name));
         assert_eq!(targets.len(), file_count + 1); // plus one for one of the twins
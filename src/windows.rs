@@ -6,13 +6,21 @@ use std::{
         raw::c_int,
         windows::{ffi::OsStrExt, prelude::*},
     },
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 
+use log::warn;
 use scopeguard::defer;
 use windows::{self, Guid, Interface, HRESULT};
 
-use crate::{into_unknown, Error, TrashContext, TrashItem};
+use crate::{
+    into_unknown,
+    os_limited::{
+        overwritten_path, renamed_restore_path, RestoreCollisionPolicy, RestoreOptions,
+        RestoreOutcome,
+    },
+    Error, TrashContext, TrashItem, TrashItemKind, TrashItemMetadata,
+};
 
 mod bindings {
     ::windows::include_bindings!();
@@ -115,6 +123,7 @@ impl TrashContext {
             };
             check_hresult! { pfo.SetOperationFlags(FOF_NO_UI | FOF_ALLOWUNDO | FOF_WANTNUKEWARNING) };
             for full_path in full_paths.iter() {
+                self.check_cancelled()?;
                 let path_prefix = ['\\' as u16, '\\' as u16, '?' as u16, '\\' as u16];
                 let mut wide_path_container: Vec<_> =
                     full_path.as_os_str().encode_wide().chain(std::iter::once(0)).collect();
@@ -137,7 +146,7 @@ impl TrashContext {
     }
 }
 
-pub fn list() -> Result<Vec<TrashItem>, Error> {
+pub fn list(_ctx: &TrashContext) -> Result<Vec<TrashItem>, Error> {
     ensure_com_initialized();
     unsafe {
         let recycle_bin: IShellFolder2 = bind_to_csidl(CSIDL_BITBUCKET as c_int)?;
@@ -177,7 +186,7 @@ pub fn list() -> Result<Vec<TrashItem>, Error> {
 
             item_vec.push(TrashItem {
                 id,
-                name: name.into_string().map_err(|original| Error::ConvertOsString { original })?,
+                name,
                 original_parent: PathBuf::from(orig_loc),
                 time_deleted: date_deleted,
             });
@@ -186,7 +195,70 @@ pub fn list() -> Result<Vec<TrashItem>, Error> {
     }
 }
 
-pub fn purge_all<I>(items: I) -> Result<(), Error>
+pub fn item_from_id(ctx: &TrashContext, id: &OsStr) -> Result<TrashItem, Error> {
+    list(ctx)?
+        .into_iter()
+        .find(|item| item.id == id)
+        .ok_or_else(|| Error::CouldNotAccess { target: id.to_string_lossy().into() })
+}
+
+pub fn list_iter(ctx: &TrashContext) -> Result<ListIter, Error> {
+    Ok(ListIter(list(ctx)?.into_iter()))
+}
+
+/// The items of the Recycle Bin are enumerated up front, so this never yields an error.
+pub struct ListIter(std::vec::IntoIter<TrashItem>);
+impl Iterator for ListIter {
+    type Item = Result<TrashItem, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(Ok)
+    }
+}
+
+pub fn metadata(item: &TrashItem) -> Result<TrashItemMetadata, Error> {
+    // The parsing name of an item in the Recycle Bin is the path of the file that stores the item
+    // in the `$Recycle.Bin` folder of its drive.
+    let path = Path::new(&item.id);
+    let metadata = path.symlink_metadata().map_err(|e| fsys_err(path, e))?;
+    let file_type = metadata.file_type();
+    let kind = if file_type.is_dir() {
+        TrashItemKind::Directory
+    } else if file_type.is_symlink() {
+        TrashItemKind::Symlink
+    } else if file_type.is_file() {
+        TrashItemKind::File
+    } else {
+        TrashItemKind::Other
+    };
+    let size = if file_type.is_dir() {
+        total_len(path).map_err(|e| fsys_err(path, e))?
+    } else {
+        metadata.len()
+    };
+    Ok(TrashItemMetadata { kind, size })
+}
+
+pub(crate) fn item_sizes(items: &[TrashItem]) -> Vec<Result<u64, Error>> {
+    items.iter().map(|item| metadata(item).map(|metadata| metadata.size)).collect()
+}
+
+/// Returns the total length of the files in the directory, recursively.
+fn total_len(path: &Path) -> Result<u64, std::io::Error> {
+    let mut result = 0;
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        let metadata = entry.path().symlink_metadata()?;
+        result += if metadata.is_dir() { total_len(&entry.path())? } else { metadata.len() };
+    }
+    Ok(result)
+}
+
+pub fn empty(ctx: &TrashContext) -> Result<(), Error> {
+    purge_all(ctx, list(ctx)?)
+}
+
+pub fn purge_all<I>(ctx: &TrashContext, items: I) -> Result<(), Error>
 where
     I: IntoIterator<Item = TrashItem>,
 {
@@ -203,6 +275,7 @@ where
         check_hresult! { pfo.SetOperationFlags(FOF_NO_UI) };
         let mut at_least_one = false;
         for item in items {
+            ctx.check_cancelled()?;
             at_least_one = true;
             let mut id_wstr: Vec<_> = item.id.encode_wide().chain(std::iter::once(0)).collect();
             let mut pidl = MaybeUninit::<*mut ITEMIDLIST>::uninit();
@@ -234,7 +307,7 @@ where
     }
 }
 
-pub fn restore_all<I>(items: I) -> Result<(), Error>
+pub fn restore_all<I>(ctx: &TrashContext, items: I) -> Result<(), Error>
 where
     I: IntoIterator<Item = TrashItem>,
 {
@@ -265,40 +338,8 @@ where
         };
         check_hresult! { pfo.SetOperationFlags(FOF_NO_UI | FOFX_EARLYFAILURE) };
         for item in items.iter() {
-            let mut id_wstr: Vec<_> = item.id.encode_wide().chain(std::iter::once(0)).collect();
-            let mut pidl = MaybeUninit::<*mut ITEMIDLIST>::uninit();
-            check_hresult! {
-                recycle_bin.ParseDisplayName(
-                    HWND::NULL,
-                    None,
-                    PWSTR(id_wstr.as_mut_ptr()),
-                    std::ptr::null_mut(),
-                    pidl.as_mut_ptr(),
-                    std::ptr::null_mut(),
-                )
-            };
-            let pidl = pidl.assume_init();
-            defer! {{ CoTaskMemFree(pidl as *mut c_void); }}
-            let trash_item_shi: IShellItem = check_res_and_get_ok! {
-                SHCreateItemWithParent(
-                    std::ptr::null_mut(),
-                    &recycle_bin,
-                    pidl,
-                )
-            };
-            let mut parent_path_wide: Vec<_> =
-                item.original_parent.as_os_str().encode_wide().chain(std::iter::once(0)).collect();
-            let orig_folder_shi: IShellItem = check_res_and_get_ok! {
-                SHCreateItemFromParsingName(
-                    PWSTR(parent_path_wide.as_mut_ptr()),
-                    None,
-                )
-            };
-            let mut name_wstr: Vec<_> = AsRef::<OsStr>::as_ref(&item.name)
-                .encode_wide()
-                .chain(std::iter::once(0))
-                .collect();
-            check_hresult! { pfo.MoveItem(trash_item_shi, orig_folder_shi, PWSTR(name_wstr.as_mut_ptr()), None) };
+            ctx.check_cancelled()?;
+            queue_restore(&recycle_bin, &pfo, item, &item.original_path())?;
         }
         if !items.is_empty() {
             check_hresult! { pfo.PerformOperations() };
@@ -307,6 +348,207 @@ where
     }
 }
 
+pub fn restore_all_with_options<I>(
+    ctx: &TrashContext,
+    items: I,
+    options: &RestoreOptions,
+) -> Result<Vec<(TrashItem, RestoreOutcome)>, Error>
+where
+    I: IntoIterator<Item = TrashItem>,
+{
+    ensure_com_initialized();
+    let recycle_bin: IShellFolder2 = unsafe { bind_to_csidl(CSIDL_BITBUCKET as i32)? };
+    let mut result = Vec::new();
+    let mut iter = items.into_iter();
+    while let Some(item) = iter.next() {
+        let (outcome, abort) = match ctx.check_cancelled() {
+            Ok(()) => {
+                let outcome = restore_one(&recycle_bin, &item, options.collision_policy);
+                // Only the `Fail` policy stops at the first item that couldn't be restored.
+                let abort = options.collision_policy == RestoreCollisionPolicy::Fail
+                    && matches!(
+                        outcome,
+                        RestoreOutcome::Collision { .. } | RestoreOutcome::Failed(_)
+                    );
+                (outcome, abort)
+            }
+            Err(error) => (RestoreOutcome::Failed(error), true),
+        };
+        result.push((item, outcome));
+        if abort {
+            result.extend(iter.map(|item| (item, RestoreOutcome::NotAttempted)));
+            break;
+        }
+    }
+    Ok(result)
+}
+
+fn restore_one(
+    recycle_bin: &IShellFolder2,
+    item: &TrashItem,
+    policy: RestoreCollisionPolicy,
+) -> RestoreOutcome {
+    let original_path = item.original_path();
+    let is_dir = Path::new(&item.id).is_dir();
+    let mut target = original_path.clone();
+    let mut attempt = 0;
+    // What was at the original location when overwriting, moved aside until the item is restored.
+    let mut overwritten: Option<PathBuf> = None;
+    // Like in `restore_all` this check is racy, in which case Windows asks the user what to do.
+    while target.symlink_metadata().is_ok() {
+        match policy {
+            RestoreCollisionPolicy::Fail => {
+                return RestoreOutcome::Collision { path: original_path }
+            }
+            RestoreCollisionPolicy::Skip => return RestoreOutcome::Skipped { path: original_path },
+            RestoreCollisionPolicy::Overwrite if overwritten.is_some() => {
+                // Something appeared again after moving the first one aside.
+                put_back_overwritten(overwritten.as_deref().unwrap(), &target);
+                return RestoreOutcome::Failed(Error::RestoreCollision {
+                    path: target,
+                    remaining_items: Vec::new(),
+                });
+            }
+            RestoreCollisionPolicy::Overwrite => {
+                let aside = overwritten_path(&target);
+                if let Err(e) = std::fs::rename(&target, &aside) {
+                    return RestoreOutcome::Failed(fsys_err(&target, e));
+                }
+                overwritten = Some(aside);
+            }
+            RestoreCollisionPolicy::Rename => {
+                attempt += 1;
+                target = renamed_restore_path(&original_path, is_dir, attempt);
+            }
+        }
+    }
+    let restored = unsafe {
+        (|| -> Result<(), Error> {
+            let pfo: IFileOperation = check_res_and_get_ok! {
+                CoCreateInstance(
+                    &FileOperation as *const _,
+                    None,
+                    CLSCTX::CLSCTX_ALL,
+                )
+            };
+            check_hresult! { pfo.SetOperationFlags(FOF_NO_UI | FOFX_EARLYFAILURE) };
+            queue_restore(recycle_bin, &pfo, item, &target)?;
+            check_hresult! { pfo.PerformOperations() };
+            Ok(())
+        })()
+    };
+    match restored {
+        Ok(()) => {
+            if let Some(aside) = &overwritten {
+                let removed = if aside.is_dir() {
+                    std::fs::remove_dir_all(aside)
+                } else {
+                    std::fs::remove_file(aside)
+                };
+                if let Err(e) = removed {
+                    warn!(
+                        "Failed to remove {:?}, which was overwritten by {:?}: {}",
+                        aside, target, e
+                    );
+                }
+            }
+            RestoreOutcome::Restored { path: target }
+        }
+        Err(e) => {
+            if let Some(aside) = &overwritten {
+                put_back_overwritten(aside, &target);
+            }
+            RestoreOutcome::Failed(e)
+        }
+    }
+}
+
+/// Moves what was overwritten back to `target` after restoring an item over it failed. It's left
+/// where it is if something took `target` in the meantime.
+fn put_back_overwritten(aside: &Path, target: &Path) {
+    if target.symlink_metadata().is_ok() {
+        warn!("{:?} was taken in the meantime, so {:?} is left there", target, aside);
+    } else if let Err(e) = std::fs::rename(aside, target) {
+        warn!(
+            "Failed to move {:?} back to {:?}, it's left there. The error was: {}",
+            aside, target, e
+        );
+    }
+}
+
+pub fn restore_to(
+    ctx: &TrashContext,
+    item: TrashItem,
+    destination: &Path,
+    new_name: Option<&OsStr>,
+) -> Result<PathBuf, Error> {
+    ctx.check_cancelled()?;
+    let target = destination.join(new_name.unwrap_or(&item.name));
+    // Like in `restore_all` this check is racy, in which case Windows asks the user what to do.
+    if target.symlink_metadata().is_ok() {
+        return Err(Error::RestoreCollision { path: target, remaining_items: vec![item] });
+    }
+    std::fs::create_dir_all(destination).map_err(|e| fsys_err(destination, e))?;
+    ensure_com_initialized();
+    unsafe {
+        let recycle_bin: IShellFolder2 = bind_to_csidl(CSIDL_BITBUCKET as i32)?;
+        let pfo: IFileOperation = check_res_and_get_ok! {
+            CoCreateInstance(
+                &FileOperation as *const _,
+                None,
+                CLSCTX::CLSCTX_ALL,
+            )
+        };
+        check_hresult! { pfo.SetOperationFlags(FOF_NO_UI | FOFX_EARLYFAILURE) };
+        queue_restore(&recycle_bin, &pfo, &item, &target)?;
+        check_hresult! { pfo.PerformOperations() };
+    }
+    Ok(target)
+}
+
+/// Adds moving `item` out of the recycle bin to `target` to the operations of `pfo`.
+unsafe fn queue_restore(
+    recycle_bin: &IShellFolder2,
+    pfo: &IFileOperation,
+    item: &TrashItem,
+    target: &Path,
+) -> Result<(), Error> {
+    let mut id_wstr: Vec<_> = item.id.encode_wide().chain(std::iter::once(0)).collect();
+    let mut pidl = MaybeUninit::<*mut ITEMIDLIST>::uninit();
+    check_hresult! {
+        recycle_bin.ParseDisplayName(
+            HWND::NULL,
+            None,
+            PWSTR(id_wstr.as_mut_ptr()),
+            std::ptr::null_mut(),
+            pidl.as_mut_ptr(),
+            std::ptr::null_mut(),
+        )
+    };
+    let pidl = pidl.assume_init();
+    defer! {{ CoTaskMemFree(pidl as *mut c_void); }}
+    let trash_item_shi: IShellItem = check_res_and_get_ok! {
+        SHCreateItemWithParent(
+            std::ptr::null_mut(),
+            recycle_bin,
+            pidl,
+        )
+    };
+    let parent = target.parent().unwrap_or(&item.original_parent);
+    let mut parent_path_wide: Vec<_> =
+        parent.as_os_str().encode_wide().chain(std::iter::once(0)).collect();
+    let orig_folder_shi: IShellItem = check_res_and_get_ok! {
+        SHCreateItemFromParsingName(
+            PWSTR(parent_path_wide.as_mut_ptr()),
+            None,
+        )
+    };
+    let mut name_wstr: Vec<_> =
+        target.file_name().unwrap_or_default().encode_wide().chain(std::iter::once(0)).collect();
+    check_hresult! { pfo.MoveItem(trash_item_shi, orig_folder_shi, PWSTR(name_wstr.as_mut_ptr()), None) };
+    Ok(())
+}
+
 unsafe fn get_display_name(
     psf: IShellFolder,
     pidl: *mut ITEMIDLIST,
@@ -498,6 +740,11 @@ impl Drop for CoInitializer {
 thread_local! {
     static CO_INITIALIZER: CoInitializer = CoInitializer::new();
 }
+/// Converts a file system error to a crate `Error`
+fn fsys_err<P: AsRef<Path>>(path: P, source: std::io::Error) -> Error {
+    Error::FileSystem { path: path.as_ref().to_owned(), source }
+}
+
 fn ensure_com_initialized() {
     CO_INITIALIZER.with(|_| {});
 }